@@ -11,6 +11,7 @@ fn main() {
         .header("mbeval/include/mbeval.h")
         .allowlist_function("mbeval_init")
         .allowlist_function("mbeval_get_mb_info")
+        .allowlist_var("MAX_PIECES_MB")
         .rustified_enum("PawnFileType")
         .rustified_enum("BishopParity")
         .rustified_enum("Side")
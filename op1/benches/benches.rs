@@ -1,5 +1,5 @@
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
-use op1::{Tablebase, Value};
+use op1::{Dtc, ProbeContext, Tablebase, Value};
 use shakmaty::{CastlingMode, Chess, fen::Fen};
 
 fn kbpkpppp(c: &mut Criterion) {
@@ -19,11 +19,51 @@ fn kbpkpppp(c: &mut Criterion) {
 
             assert_eq!(
                 tablebase.probe(&pos).unwrap(),
-                black_box(Some(Value::Dtc(-1)))
+                black_box(Some(Value::Dtc(Dtc::new(-1))))
             );
         });
     });
 }
 
-criterion_group!(benches, kbpkpppp);
+fn kbpkpppp_with_context(c: &mut Criterion) {
+    c.bench_function("kbpkpppp_with_context", |b| {
+        let mut tablebase = Tablebase::new();
+        tablebase.add_path("../tables").unwrap();
+        let mut ctx = ProbeContext::new().unwrap();
+
+        let pos: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1"
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+
+        b.iter(|| {
+            assert_eq!(
+                tablebase
+                    .probe_with_context(black_box(&pos), &mut ctx)
+                    .unwrap(),
+                black_box(Some(Value::Dtc(Dtc::new(-1))))
+            );
+        });
+    });
+}
+
+fn unloaded_material(c: &mut Criterion) {
+    c.bench_function("unloaded_material", |b| {
+        let mut tablebase = Tablebase::new();
+        tablebase.add_path("../tables").unwrap();
+
+        let pos: Chess = "8/8/8/8/8/8/QQQQQ3/K6k w - - 0 1"
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+
+        b.iter(|| {
+            assert_eq!(tablebase.probe(black_box(&pos)).unwrap(), None);
+        });
+    });
+}
+
+criterion_group!(benches, kbpkpppp, kbpkpppp_with_context, unloaded_material);
 criterion_main!(benches);
@@ -0,0 +1,234 @@
+//! Builtin solver for KQvK and KRvK, see
+//! [`Tablebase::set_builtin_solver()`](crate::Tablebase::set_builtin_solver).
+
+use std::sync::OnceLock;
+
+use shakmaty::{
+    Bitboard, Chess, Color, Position as _, Role, Square,
+    attacks::{king_attacks, queen_attacks, rook_attacks},
+};
+
+use crate::tablebase::{Dtc, Value};
+
+const UNKNOWN: u8 = u8::MAX;
+
+/// DTC of all positions with the stronger side (king and one piece) as
+/// white, indexed by white king, white piece, and black king.
+struct Solution {
+    /// Win in n moves, with white to move.
+    white_to_move: Vec<u8>,
+    /// Loss in n moves (0 if checkmated), with black to move.
+    black_to_move: Vec<u8>,
+}
+
+fn index(wk: Square, wp: Square, bk: Square) -> usize {
+    usize::from(wk) * 64 * 64 + usize::from(wp) * 64 + usize::from(bk)
+}
+
+fn piece_attacks(role: Role, sq: Square, occupied: Bitboard) -> Bitboard {
+    match role {
+        Role::Queen => queen_attacks(sq, occupied),
+        _ => rook_attacks(sq, occupied),
+    }
+}
+
+fn is_valid(wk: Square, wp: Square, bk: Square) -> bool {
+    wk != wp && wk != bk && wp != bk && !king_attacks(wk).contains(bk)
+}
+
+/// Squares attacked by white, with the black king removed from the board, so
+/// that it cannot hide behind itself.
+fn white_attacks(role: Role, wk: Square, wp: Square) -> Bitboard {
+    king_attacks(wk) | piece_attacks(role, wp, Bitboard::from(wk))
+}
+
+/// Black king moves, not including the capture of the white piece.
+fn black_moves(role: Role, wk: Square, wp: Square, bk: Square) -> Bitboard {
+    king_attacks(bk) & !white_attacks(role, wk, wp) & !Bitboard::from(wp)
+}
+
+fn can_capture(wk: Square, wp: Square, bk: Square) -> bool {
+    king_attacks(bk).contains(wp) && !king_attacks(wk).contains(wp)
+}
+
+fn is_check(role: Role, wk: Square, wp: Square, bk: Square) -> bool {
+    piece_attacks(role, wp, Bitboard::from(wk)).contains(bk)
+}
+
+fn solve(role: Role) -> Solution {
+    let mut solution = Solution {
+        white_to_move: vec![UNKNOWN; 64 * 64 * 64],
+        black_to_move: vec![UNKNOWN; 64 * 64 * 64],
+    };
+
+    for wk in Square::ALL {
+        for wp in Square::ALL {
+            for bk in Square::ALL {
+                if is_valid(wk, wp, bk)
+                    && is_check(role, wk, wp, bk)
+                    && black_moves(role, wk, wp, bk).is_empty()
+                    && !can_capture(wk, wp, bk)
+                {
+                    solution.black_to_move[index(wk, wp, bk)] = 0;
+                }
+            }
+        }
+    }
+
+    for n in 1..UNKNOWN {
+        let mut changed = false;
+
+        for wk in Square::ALL {
+            for wp in Square::ALL {
+                for bk in Square::ALL {
+                    let i = index(wk, wp, bk);
+                    if !is_valid(wk, wp, bk)
+                        || is_check(role, wk, wp, bk)
+                        || solution.white_to_move[i] != UNKNOWN
+                    {
+                        continue;
+                    }
+                    let occupied = Bitboard::from(wk) | Bitboard::from(wp) | Bitboard::from(bk);
+                    let king_moves = king_attacks(wk) & !occupied & !king_attacks(bk);
+                    let piece_moves = piece_attacks(role, wp, occupied) & !occupied;
+                    if king_moves
+                        .into_iter()
+                        .map(|to| index(to, wp, bk))
+                        .chain(piece_moves.into_iter().map(|to| index(wk, to, bk)))
+                        .any(|j| solution.black_to_move[j] == n - 1)
+                    {
+                        solution.white_to_move[i] = n;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        for wk in Square::ALL {
+            for wp in Square::ALL {
+                for bk in Square::ALL {
+                    let i = index(wk, wp, bk);
+                    if !is_valid(wk, wp, bk)
+                        || solution.black_to_move[i] != UNKNOWN
+                        || can_capture(wk, wp, bk)
+                    {
+                        continue;
+                    }
+                    let moves = black_moves(role, wk, wp, bk);
+                    if moves.is_empty() {
+                        continue; // Stalemate
+                    }
+                    let mut max = 0;
+                    for to in moves {
+                        max = max.max(solution.white_to_move[index(wk, wp, to)]);
+                    }
+                    if max == n {
+                        solution.black_to_move[i] = n;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    solution
+}
+
+fn solution(role: Role) -> &'static Solution {
+    static QUEEN: OnceLock<Solution> = OnceLock::new();
+    static ROOK: OnceLock<Solution> = OnceLock::new();
+    match role {
+        Role::Queen => QUEEN.get_or_init(|| solve(Role::Queen)),
+        _ => ROOK.get_or_init(|| solve(Role::Rook)),
+    }
+}
+
+/// Probes KQvK and KRvK positions without tables. Returns `None` for other
+/// material. Checkmate is reported as `Dtc(0)`, like the tables do.
+pub(crate) fn probe(pos: &Chess) -> Option<Value> {
+    let board = pos.board();
+    if board.occupied().count() != 3 {
+        return None;
+    }
+    let strong = Color::ALL
+        .into_iter()
+        .find(|&color| board.by_color(color).more_than_one())?;
+    let role = [Role::Queen, Role::Rook]
+        .into_iter()
+        .find(|&role| (board.by_color(strong) & board.by_role(role)).any())?;
+
+    // Orient the board so that the stronger side is white.
+    let orient = |sq: Square| strong.fold_wb(sq, sq.flip_vertical());
+    let wk = orient(board.king_of(strong)?);
+    let wp = orient((board.by_color(strong) & board.by_role(role)).first()?);
+    let bk = orient(board.king_of(!strong)?);
+
+    let solution = solution(role);
+    let i = index(wk, wp, bk);
+    Some(if pos.turn() == strong {
+        match solution.white_to_move[i] {
+            UNKNOWN => Value::Draw,
+            n => Value::Dtc(Dtc::new(i32::from(n))),
+        }
+    } else {
+        match solution.black_to_move[i] {
+            UNKNOWN => Value::Draw,
+            n => Value::Dtc(Dtc::new(-i32::from(n))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::{CastlingMode, fen::Fen};
+
+    use super::*;
+
+    fn probe_fen(fen: &str) -> Option<Value> {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Standard)
+            .unwrap();
+        probe(&pos)
+    }
+
+    #[test]
+    fn test_longest_wins() {
+        // The longest KQvK and KRvK wins take 10 and 16 moves.
+        let longest = |role| {
+            solution(role)
+                .white_to_move
+                .iter()
+                .filter(|&&n| n != UNKNOWN)
+                .max()
+        };
+        assert_eq!(longest(Role::Queen), Some(&10));
+        assert_eq!(longest(Role::Rook), Some(&16));
+    }
+
+    #[test]
+    fn test_probe() {
+        assert_eq!(
+            probe_fen("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1"),
+            Some(Value::Dtc(Dtc::new(0)))
+        );
+        assert_eq!(
+            probe_fen("k7/6Q1/1K6/8/8/8/8/8 w - - 0 1"),
+            Some(Value::Dtc(Dtc::new(1)))
+        );
+        assert_eq!(
+            probe_fen("8/8/8/8/8/1k6/6q1/K7 b - - 0 1"),
+            Some(Value::Dtc(Dtc::new(1)))
+        );
+        assert_eq!(
+            probe_fen("k7/1Q6/8/8/8/8/8/7K b - - 0 1"),
+            Some(Value::Draw)
+        );
+        assert_eq!(probe_fen("k7/8/1K6/8/8/8/8/1B6 w - - 0 1"), None);
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+
+use rustc_hash::FxHashSet;
+use shakmaty::ByRole;
+
+use crate::tablebase::Material;
+
+/// Loaded endgames with a given number of pieces, see
+/// [`Tablebase::coverage()`](crate::Tablebase::coverage).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    /// Number of pieces, including kings.
+    pub pieces: usize,
+    /// Number of distinct endgames with at least one table loaded. An
+    /// endgame and its color-flipped counterpart count as one.
+    pub loaded: usize,
+    /// Number of possible endgames, excluding KvK, KBvK and KNvK.
+    pub possible: usize,
+}
+
+impl Coverage {
+    pub fn is_complete(&self) -> bool {
+        self.loaded >= self.possible
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}p: {}/{}", self.pieces, self.loaded, self.possible)?;
+        if self.is_complete() {
+            f.write_str(" (complete)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Pawns, knights, bishops, rooks, and queens of one side.
+type SideKey = [u8; 5];
+
+/// Identifies an endgame regardless of which side is white.
+type EndgameKey = (SideKey, SideKey);
+
+fn side_key(side: &ByRole<u8>) -> SideKey {
+    [side.pawn, side.knight, side.bishop, side.rook, side.queen]
+}
+
+pub(crate) fn endgame_key(material: &Material) -> EndgameKey {
+    let white = side_key(&material.white);
+    let black = side_key(&material.black);
+    (white.max(black), white.min(black))
+}
+
+pub(crate) fn num_pieces(key: &EndgameKey) -> usize {
+    key.0
+        .iter()
+        .chain(key.1.iter())
+        .map(|&n| usize::from(n))
+        .sum::<usize>()
+        + 2
+}
+
+fn is_trivial(key: &EndgameKey) -> bool {
+    let [pawns, knights, bishops, rooks, queens] = [0, 1, 2, 3, 4].map(|i| key.0[i] + key.1[i]);
+    pawns == 0 && rooks == 0 && queens == 0 && knights + bishops <= 1
+}
+
+/// All sides with exactly `n` pieces besides the king.
+fn sides(n: u8) -> Vec<SideKey> {
+    let mut sides = Vec::new();
+    for pawns in 0..=n {
+        for knights in 0..=(n - pawns) {
+            for bishops in 0..=(n - pawns - knights) {
+                for rooks in 0..=(n - pawns - knights - bishops) {
+                    let queens = n - pawns - knights - bishops - rooks;
+                    sides.push([pawns, knights, bishops, rooks, queens]);
+                }
+            }
+        }
+    }
+    sides
+}
+
+/// Counts the possible non-trivial endgames with the given number of
+/// pieces, including kings.
+pub(crate) fn num_possible(pieces: usize) -> usize {
+    let Some(n) = pieces.checked_sub(2).and_then(|n| u8::try_from(n).ok()) else {
+        return 0;
+    };
+
+    let mut endgames = FxHashSet::default();
+    for white in 0..=n {
+        for a in sides(white) {
+            for b in sides(n - white) {
+                let key = (a.max(b), a.min(b));
+                if !is_trivial(&key) {
+                    endgames.insert(key);
+                }
+            }
+        }
+    }
+    endgames.len()
+}
+
+/// Placements of the two kings that mbeval distinguishes, after reducing
+/// by symmetry.
+const KK_POSITIONS: usize = 1806;
+const KK_POSITIONS_NO_PAWNS: usize = 462;
+
+/// Number of sub-table directories and table files of an endgame, see
+/// [`expected_subtable_count()`] and
+/// [`Tablebase::loaded_subtable_count()`](crate::Tablebase::loaded_subtable_count).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SubtableCount {
+    /// Sub-table directories: the unconstrained one like `kqk_out`, and
+    /// those constrained by pawn files (like `kpkp_op1_out`) or bishop
+    /// parity (like `kbbk_wbe_out`).
+    pub directories: usize,
+    /// `.mb` files in all of these directories.
+    pub mb_files: usize,
+    /// `.hi` files in all of these directories.
+    pub high_dtc_files: usize,
+}
+
+/// Counts the sub-tables of a complete download of `material`, to detect
+/// partial downloads. Each directory has one `.mb` file per side to move and
+/// placement of the kings.
+///
+/// Pawn file sub-tables exist for opposing or blocked pawns, as classified
+/// by `GetMBPosition()` in `mbeval.c` (lines 4394-4557) and probed by
+/// `GetMBInfo()` (lines 4681-4893). Only 1 vs 1 (`bp1`, `op1`) and 2 vs 2
+/// pawns (`dp2`, `op22`) have two such types.
+///
+/// Bishop parity sub-tables exist for pawnless sides with 2 or 3 bishops,
+/// which are even or odd (lines 4902-4933), in every combination with the
+/// unconstrained other side (lines 4959-4988).
+///
+/// `.hi` files only exist where positions with high DTC need them, so
+/// `high_dtc_files` is an upper bound of one per `.mb` file.
+pub fn expected_subtable_count(material: &Material) -> SubtableCount {
+    let pawns = (material.white.pawn, material.black.pawn);
+    let directories = if pawns == (0, 0) {
+        let parities = |bishops: u8| if matches!(bishops, 2 | 3) { 3 } else { 1 };
+        parities(material.white.bishop) * parities(material.black.bishop)
+    } else {
+        1 + match pawns {
+            (1, 1) | (2, 2) => 2, // bp1 and op1, or dp2 and op22
+            (2, 1) | (1, 2) | (3, 1) | (1, 3) | (4, 1) | (1, 4) => 1,
+            (3, 2) | (2, 3) | (3, 3) | (4, 2) | (2, 4) => 1,
+            _ => 0,
+        }
+    };
+    let kk_positions = if pawns == (0, 0) {
+        KK_POSITIONS_NO_PAWNS
+    } else {
+        KK_POSITIONS
+    };
+    let mb_files = directories * 2 * kk_positions;
+    SubtableCount {
+        directories,
+        mb_files,
+        high_dtc_files: mb_files,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tablebase::parse_material;
+
+    fn count(name: &str) -> SubtableCount {
+        expected_subtable_count(&parse_material(name).unwrap())
+    }
+
+    #[test]
+    fn test_expected_subtable_count() {
+        assert_eq!(
+            count("kqk"),
+            SubtableCount {
+                directories: 1,
+                mb_files: 924,
+                high_dtc_files: 924,
+            }
+        );
+        assert_eq!(count("kqkp").directories, 1);
+        assert_eq!(count("kqkp").mb_files, 3612);
+        assert_eq!(count("kpkp").directories, 3);
+        assert_eq!(count("kpkp").mb_files, 10836);
+        assert_eq!(count("kppkpp").directories, 3);
+        assert_eq!(count("krppkpp").directories, 3);
+        assert_eq!(count("kpppppkp").directories, 1);
+        assert_eq!(count("kbbk").directories, 3);
+        assert_eq!(count("kbbk").mb_files, 2772);
+        assert_eq!(count("kbbkbb").directories, 9);
+        assert_eq!(count("kbbpk").directories, 1);
+        assert_eq!(count("kbkb").directories, 1);
+    }
+
+    #[test]
+    fn test_num_possible() {
+        assert_eq!(num_possible(2), 0);
+        assert_eq!(num_possible(3), 3); // KPK, KRK, KQK
+        assert_eq!(num_possible(4), 30);
+    }
+}
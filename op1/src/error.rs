@@ -0,0 +1,151 @@
+use std::{error::Error, fmt, io, path::PathBuf};
+
+use shakmaty::{Chess, Move, PositionError, fen::ParseFenError};
+
+/// Error of the probe API, e.g. [`Tablebase::probe()`](crate::Tablebase::probe).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProbeError {
+    /// Reading or opening a table failed.
+    Tablebase(TablebaseError),
+    Fen(ParseFenError),
+    /// Boxed, because positions are large.
+    Position(Box<PositionError<Chess>>),
+    /// A principal variation did not reach a conversion within the number
+    /// of plies implied by its DTC. Contains the partial line.
+    NonTerminatingLine {
+        line: Vec<Move>,
+    },
+    /// Opening tables took longer than the given timeout.
+    Timeout,
+    /// A SAN move was invalid, illegal, or ambiguous. `ply` is its index in
+    /// the line.
+    San {
+        ply: usize,
+        san: String,
+    },
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Tablebase(err) => err.fmt(f),
+            ProbeError::Fen(err) => write!(f, "invalid fen: {err}"),
+            ProbeError::Position(err) => write!(f, "illegal position: {err}"),
+            ProbeError::NonTerminatingLine { line } => write!(
+                f,
+                "line did not reach a conversion after {} plies",
+                line.len()
+            ),
+            ProbeError::Timeout => f.write_str("timed out opening tables"),
+            ProbeError::San { ply, san } => {
+                write!(f, "invalid or ambiguous san at ply {ply}: {san}")
+            }
+        }
+    }
+}
+
+impl Error for ProbeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProbeError::Tablebase(err) => Some(err),
+            ProbeError::Fen(err) => Some(err),
+            ProbeError::Position(err) => Some(err),
+            ProbeError::NonTerminatingLine { .. }
+            | ProbeError::Timeout
+            | ProbeError::San { .. } => None,
+        }
+    }
+}
+
+impl From<ParseFenError> for ProbeError {
+    fn from(err: ParseFenError) -> ProbeError {
+        ProbeError::Fen(err)
+    }
+}
+
+impl From<PositionError<Chess>> for ProbeError {
+    fn from(err: PositionError<Chess>) -> ProbeError {
+        ProbeError::Position(Box::new(err))
+    }
+}
+
+impl From<TablebaseError> for ProbeError {
+    fn from(err: TablebaseError) -> ProbeError {
+        ProbeError::Tablebase(err)
+    }
+}
+
+/// Recovers errors that were passed through [`io::Error`] internally.
+impl From<io::Error> for ProbeError {
+    fn from(err: io::Error) -> ProbeError {
+        ProbeError::Tablebase(err.into())
+    }
+}
+
+/// Error when adding or reading tables.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TablebaseError {
+    Io(io::Error),
+    /// A path given to [`Tablebase::add_path()`](crate::Tablebase::add_path)
+    /// is not a directory.
+    InvalidPath {
+        path: PathBuf,
+    },
+    /// A table file is corrupt or truncated.
+    CorruptTable {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A table file uses a compression method that is not supported.
+    UnsupportedEncoding {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TablebaseError::Io(err) => write!(f, "io error: {err}"),
+            TablebaseError::InvalidPath { path } => {
+                write!(f, "not a table directory: {}", path.display())
+            }
+            TablebaseError::CorruptTable { path, source } => {
+                write!(f, "corrupt table {}: {source}", path.display())
+            }
+            TablebaseError::UnsupportedEncoding { path } => {
+                write!(f, "unsupported compression in {}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for TablebaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TablebaseError::Io(err) | TablebaseError::CorruptTable { source: err, .. } => Some(err),
+            TablebaseError::InvalidPath { .. } | TablebaseError::UnsupportedEncoding { .. } => None,
+        }
+    }
+}
+
+/// Recovers errors that were passed through [`io::Error`] internally.
+impl From<io::Error> for TablebaseError {
+    fn from(err: io::Error) -> TablebaseError {
+        err.downcast().unwrap_or_else(TablebaseError::Io)
+    }
+}
+
+impl From<TablebaseError> for io::Error {
+    fn from(err: TablebaseError) -> io::Error {
+        match err {
+            TablebaseError::Io(err) => err,
+            TablebaseError::InvalidPath { .. } => io::Error::new(io::ErrorKind::InvalidInput, err),
+            TablebaseError::CorruptTable { ref source, .. } => io::Error::new(source.kind(), err),
+            TablebaseError::UnsupportedEncoding { .. } => {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            }
+        }
+    }
+}
@@ -1,5 +1,22 @@
+mod builtin;
+mod coverage;
 mod decompressor;
+mod error;
+mod mb_info;
+mod report;
 mod table;
 mod tablebase;
 
-pub use tablebase::{Tablebase, Value};
+pub use coverage::{Coverage, SubtableCount, expected_subtable_count};
+pub use error::{ProbeError, TablebaseError};
+pub use mb_info::MbInfoSnapshot;
+pub use mbeval_sys::{BishopParity, PawnFileType};
+pub use report::{AddPathReport, VerifyReport};
+pub use table::{ProbeContext, ReadSeek, SideValue, TableType};
+#[cfg(feature = "tokio")]
+pub use tablebase::ProbeStream;
+pub use tablebase::{
+    BucketedValue, CorruptPolicy, DrawKind, Dtc, FiftyMoveRule, GameResult, Material, MoveVerdict,
+    Phase, ProbeOutcome, SignedValue, TableKey, Tablebase, TablebaseDiff, TieBreak, Value, Wdl,
+    bishop_parity, material_string,
+};
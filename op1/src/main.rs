@@ -70,6 +70,15 @@ impl From<io::Error> for ProbeError {
     }
 }
 
+impl From<op1::ProbeError> for ProbeError {
+    fn from(err: op1::ProbeError) -> Self {
+        match err {
+            op1::ProbeError::Position(err) => ProbeError::Position(*err),
+            err => ProbeError::Io(io::Error::other(err)),
+        }
+    }
+}
+
 #[axum::debug_handler]
 async fn handle_probe(
     State(app): State<&'static AppState>,
@@ -127,6 +136,7 @@ async fn handle_monitor(State(app): State<&'static AppState>) -> String {
         format!("draws={}u", stats.draws()),
         format!("true_predictions={}u", stats.true_predictions()),
         format!("false_predictions={}u", stats.false_predictions()),
+        format!("flipped_probes={}u", stats.flipped_probes()),
     ];
     format!("op1 {}", metrics.join(","))
 }
@@ -151,8 +161,8 @@ async fn main() {
     // Initialize tablebase
     let mut tablebase = Tablebase::new();
     for path in opt.path {
-        let num = tablebase.add_path(&path).expect("add path");
-        tracing::info!("loaded {} tables from {}", num, path.display());
+        let report = tablebase.add_path_report(&path).expect("add path");
+        tracing::info!("{report}");
     }
 
     // Start server
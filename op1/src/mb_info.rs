@@ -0,0 +1,63 @@
+use mbeval_sys::{BishopParity, MbInfo, PawnFileType, ZIndex};
+
+/// Owned copy of the indices mbeval computed for a position, see
+/// [`Tablebase::mb_info()`](crate::Tablebase::mb_info).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MbInfoSnapshot {
+    pub kk_index: i32,
+    pub num_pieces: i32,
+    /// Index without parity constraints, followed by the indices into
+    /// bishop parity constrained sub-tables (for white and black).
+    pub parity_indices: Vec<(ZIndex, [BishopParity; 2])>,
+    pub pawn_file_type: PawnFileType,
+    /// Indices into sub-tables with constrained pawn files, in the order
+    /// `Bp11`, `Op11`, `Op21`, `Op12`, `Dp22`, `Op22`, `Op31`, `Op13`,
+    /// `Op41`, `Op14`, `Op32`, `Op23`, `Op33`, `Op42`, `Op24`. Not
+    /// applicable sub-tables have index `!0`.
+    pub pawn_file_indices: [(PawnFileType, ZIndex); 15],
+}
+
+impl From<&MbInfo> for MbInfoSnapshot {
+    fn from(mb_info: &MbInfo) -> MbInfoSnapshot {
+        MbInfoSnapshot {
+            kk_index: mb_info.kk_index,
+            num_pieces: mb_info.num_pieces,
+            parity_indices: mb_info.parity_index[..num_parities(mb_info)]
+                .iter()
+                .map(|parity_index| (parity_index.index, parity_index.bishop_parity))
+                .collect(),
+            pawn_file_type: mb_info.pawn_file_type,
+            pawn_file_indices: [
+                (PawnFileType::Bp11, mb_info.index_bp_11),
+                (PawnFileType::Op11, mb_info.index_op_11),
+                (PawnFileType::Op21, mb_info.index_op_21),
+                (PawnFileType::Op12, mb_info.index_op_12),
+                (PawnFileType::Dp22, mb_info.index_dp_22),
+                (PawnFileType::Op22, mb_info.index_op_22),
+                (PawnFileType::Op31, mb_info.index_op_31),
+                (PawnFileType::Op13, mb_info.index_op_13),
+                (PawnFileType::Op41, mb_info.index_op_41),
+                (PawnFileType::Op14, mb_info.index_op_14),
+                (PawnFileType::Op32, mb_info.index_op_32),
+                (PawnFileType::Op23, mb_info.index_op_23),
+                (PawnFileType::Op33, mb_info.index_op_33),
+                (PawnFileType::Op42, mb_info.index_op_42),
+                (PawnFileType::Op24, mb_info.index_op_24),
+            ],
+        }
+    }
+}
+
+/// Number of valid entries in `mb_info.parity_index`, clamped to the size of
+/// the array in case of a mismatched mbeval version.
+pub(crate) fn num_parities(mb_info: &MbInfo) -> usize {
+    let len = mb_info.parity_index.len();
+    let num_parities = usize::try_from(mb_info.num_parities).unwrap_or(0);
+    if num_parities > len || mb_info.num_parities < 0 {
+        tracing::warn!(
+            num_parities = mb_info.num_parities,
+            "unexpected number of parities"
+        );
+    }
+    num_parities.min(len)
+}
@@ -0,0 +1,113 @@
+use std::{fmt, path::PathBuf};
+
+use crate::{error::TablebaseError, table::TableType};
+
+/// Summary of [`Tablebase::add_path_report()`](crate::Tablebase::add_path_report).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddPathReport {
+    pub path: PathBuf,
+    /// Number of added `.mb` tables.
+    pub mb_tables: usize,
+    /// Number of added `.hi` tables.
+    pub high_dtc_tables: usize,
+    /// Number of distinct materials among the added tables.
+    pub materials: usize,
+    /// Number of files in table directories that were not added, because
+    /// they were unreadable, filtered, duplicates, or not tables at all.
+    pub skipped: usize,
+}
+
+impl AddPathReport {
+    pub(crate) fn new(path: PathBuf) -> AddPathReport {
+        AddPathReport {
+            path,
+            mb_tables: 0,
+            high_dtc_tables: 0,
+            materials: 0,
+            skipped: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, table_type: Option<TableType>) {
+        match table_type {
+            Some(TableType::Mb) => self.mb_tables += 1,
+            Some(TableType::HighDtc) => self.high_dtc_tables += 1,
+            None => self.skipped += 1,
+        }
+    }
+
+    /// Total number of added table files.
+    pub fn tables(&self) -> usize {
+        self.mb_tables + self.high_dtc_tables
+    }
+}
+
+impl fmt::Display for AddPathReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "added {} tables ({} .mb, {} .hi) for {} materials from {}",
+            self.tables(),
+            self.mb_tables,
+            self.high_dtc_tables,
+            self.materials,
+            self.path.display()
+        )?;
+        if self.skipped > 0 {
+            write!(f, ", skipped {} files", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of [`Tablebase::verify()`](crate::Tablebase::verify).
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// Number of tables that could be opened.
+    pub opened: usize,
+    /// Tables that could not be opened, sorted by path.
+    pub failed: Vec<(PathBuf, TablebaseError)>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "opened {} tables", self.opened)?;
+        if !self.failed.is_empty() {
+            write!(f, ", {} failed", self.failed.len())?;
+            for (path, err) in &self.failed {
+                write!(f, "\n{}: {err}", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_add_path_report() {
+        let mut report = AddPathReport::new(PathBuf::from("tables"));
+        report.record(Some(TableType::Mb));
+        report.record(Some(TableType::Mb));
+        report.record(Some(TableType::HighDtc));
+        report.materials = 1;
+        assert_eq!(
+            report.to_string(),
+            "added 3 tables (2 .mb, 1 .hi) for 1 materials from tables"
+        );
+
+        report.record(None);
+        assert_eq!(
+            report.to_string(),
+            "added 3 tables (2 .mb, 1 .hi) for 1 materials from tables, skipped 1 files"
+        );
+    }
+}
@@ -2,27 +2,90 @@ use std::{
     ffi::c_int,
     fs::File,
     io,
-    io::Read,
+    io::{Read, Seek, SeekFrom},
     mem,
     num::NonZeroU32,
-    os::{fd::AsRawFd as _, unix::fs::FileExt as _},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Instant,
 };
 
+#[cfg(unix)]
+use libc::{POSIX_FADV_NOREUSE, POSIX_FADV_RANDOM, POSIX_FADV_WILLNEED};
 use mbeval_sys::ZIndex;
+use memmap2::Mmap;
+#[cfg(feature = "serde")]
+use serde::Serialize;
 use zerocopy::{
     FromBytes, FromZeros, Immutable, IntoBytes,
     little_endian::{I32, U32, U64},
 };
 
-use crate::decompressor::Decompressor;
+use crate::{decompressor::Decompressor, error::TablebaseError};
+
+// Advice is ignored on other platforms.
+#[cfg(not(unix))]
+const POSIX_FADV_NOREUSE: c_int = 0;
+#[cfg(not(unix))]
+const POSIX_FADV_RANDOM: c_int = 0;
+#[cfg(not(unix))]
+const POSIX_FADV_WILLNEED: c_int = 0;
+
+/// Custom source of table data, see
+/// [`Tablebase::with_reader_factory()`](crate::Tablebase::with_reader_factory).
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+enum Source {
+    File(File),
+    Mmap(Mmap),
+    Reader(Mutex<Box<dyn ReadSeek>>),
+}
+
+impl Source {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        match self {
+            Source::File(file) => read_exact_at(file, buf, offset),
+            Source::Mmap(mmap) => {
+                let data = usize::try_from(offset)
+                    .ok()
+                    .and_then(|start| mmap.get(start..)?.get(..buf.len()))
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::UnexpectedEof, "read past end of table")
+                    })?;
+                buf.copy_from_slice(data);
+                Ok(())
+            }
+            Source::Reader(reader) => {
+                let mut reader = reader.lock().unwrap_or_else(PoisonError::into_inner);
+                reader.seek(SeekFrom::Start(offset))?;
+                reader.read_exact(buf)
+            }
+        }
+    }
+
+    fn advise(&self, offset: u64, len: u64, advice: c_int) -> io::Result<()> {
+        match self {
+            Source::File(file) => fadvise_range(file, offset, len, advice),
+            Source::Mmap(mmap) => madvise_range(mmap, offset, len, advice),
+            Source::Reader(_) => Ok(()),
+        }
+    }
+}
 
 pub(crate) struct Table {
     table_type: TableType,
-    file: File,
+    path: PathBuf,
+    source: Source,
     header: Header,
     offsets: Box<[U64]>,
     starting_indices: Box<[U64]>,
+    read_ahead: u32,
+    last_block: AtomicU32,
 }
 
 impl Table {
@@ -30,55 +93,90 @@ impl Table {
         tracing::trace!("try open table: {}", path.display());
 
         let mut file = File::open(path)?;
-        fadvise(&file, libc::POSIX_FADV_NOREUSE)?;
+        fadvise(&file, POSIX_FADV_NOREUSE)?;
+        let index =
+            read_index(&mut file, table_type, path).map_err(|error| table_error(path, error))?;
+        fadvise(&file, POSIX_FADV_RANDOM)?;
 
-        let header = Header::try_from(RawHeader::read_from_io(&mut file)?)?;
+        Ok(Table::new(table_type, path, Source::File(file), index))
+    }
 
-        if header.list_element_size != table_type.list_element_size() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "unpexected list element size {} for {}",
-                    header.list_element_size,
-                    path.display(),
+    /// Opens a table and maps it into memory, so that probes do not need a
+    /// system call to read blocks.
+    pub(crate) fn open_mmap(path: &Path, table_type: TableType) -> io::Result<Table> {
+        tracing::trace!("try open table as mmap: {}", path.display());
+
+        let file = File::open(path)?;
+        // Safety: The mapping is owned by the table, and tables are not
+        // expected to be modified while in use.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let index = read_index(&mut &mmap[..], table_type, path)
+            .map_err(|error| table_error(path, error))?;
+        let end = index.offsets.last().copied().map_or(0, u64::from);
+        if end > mmap.len() as u64 {
+            return Err(table_error(
+                path,
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "blocks end at {end}, but file has only {} bytes",
+                        mmap.len()
+                    ),
                 ),
             ));
         }
 
-        if u32::from(header.block_size) % u32::from(table_type.list_element_size()) != 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "block size {} not cleanly divisible by list element size",
-                    header.block_size
-                ),
-            ));
-        }
+        Ok(Table::new(table_type, path, Source::Mmap(mmap), index))
+    }
 
-        let mut offsets = <[U64]>::new_box_zeroed_with_elems(header.num_blocks as usize + 1)
-            .expect("allocate offsets vector");
-        file.read_exact(offsets.as_mut_bytes())?;
-
-        let starting_indices = match table_type {
-            TableType::Mb => Box::default(),
-            TableType::HighDtc => {
-                let mut starting_indices =
-                    <[U64]>::new_box_zeroed_with_elems(header.num_blocks as usize + 1)
-                        .expect("allocate starting indices vector");
-                file.read_exact(starting_indices.as_mut_bytes())?;
-                starting_indices
-            }
-        };
+    /// Opens a table from a custom reader. `path` is only used in error
+    /// messages.
+    pub(crate) fn open_reader(
+        mut reader: Box<dyn ReadSeek>,
+        table_type: TableType,
+        path: &Path,
+    ) -> io::Result<Table> {
+        tracing::trace!("try open table from reader: {}", path.display());
 
-        fadvise(&file, libc::POSIX_FADV_RANDOM)?;
+        reader.seek(SeekFrom::Start(0))?;
+        let index =
+            read_index(&mut reader, table_type, path).map_err(|error| table_error(path, error))?;
 
-        Ok(Table {
+        Ok(Table::new(
             table_type,
-            file,
-            header,
-            offsets,
-            starting_indices,
-        })
+            path,
+            Source::Reader(Mutex::new(reader)),
+            index,
+        ))
+    }
+
+    fn new(table_type: TableType, path: &Path, source: Source, index: Index) -> Table {
+        Table {
+            table_type,
+            path: path.to_owned(),
+            source,
+            header: index.header,
+            offsets: index.offsets,
+            starting_indices: index.starting_indices,
+            read_ahead: 0,
+            last_block: AtomicU32::new(u32::MAX),
+        }
+    }
+
+    /// When a block directly follows the previously read block, advise the
+    /// kernel to load the next `blocks` blocks in the background.
+    pub(crate) fn with_read_ahead(mut self, blocks: u32) -> Table {
+        self.read_ahead = blocks;
+        self
+    }
+
+    /// Reads only the maximum DTC from the header of a table.
+    pub(crate) fn read_max_dtc(reader: impl Read) -> io::Result<u32> {
+        Ok(u32::from(RawHeader::read_from_io(reader)?.max_dtc))
+    }
+
+    pub(crate) fn max_dtc(&self) -> u32 {
+        self.header.max_dtc
     }
 
     fn block_offset(&self, block_index: u32) -> io::Result<u64> {
@@ -101,15 +199,40 @@ impl Table {
                 io::Error::new(io::ErrorKind::InvalidData, "block offsets not monotonic")
             })?;
 
+        if self.read_ahead > 0 {
+            self.read_ahead_after(block_index, compressed_block_end)?;
+        }
+
         ctx.compressed_block
             .resize(compressed_block_size as usize, 0);
-        self.file
+        self.source
             .read_exact_at(&mut ctx.compressed_block[..], compressed_block_start)
     }
 
+    fn read_ahead_after(&self, block_index: u32, block_end: u64) -> io::Result<()> {
+        let last_block = self.last_block.swap(block_index, Ordering::Relaxed);
+        if last_block.wrapping_add(1) != block_index {
+            return Ok(());
+        }
+        let window_end_index = block_index
+            .saturating_add(1)
+            .saturating_add(self.read_ahead)
+            .min(self.header.num_blocks);
+        let window_end = self.block_offset(window_end_index)?;
+        self.source.advise(
+            block_end,
+            window_end.saturating_sub(block_end),
+            POSIX_FADV_WILLNEED,
+        )
+    }
+
     pub(crate) fn read_mb(&self, index: ZIndex, ctx: &mut ProbeContext) -> io::Result<MbValue> {
         assert_eq!(self.table_type, TableType::Mb);
+        self.read_mb_value(index, ctx)
+            .map_err(|error| table_error(&self.path, error))
+    }
 
+    fn read_mb_value(&self, index: ZIndex, ctx: &mut ProbeContext) -> io::Result<MbValue> {
         let block_index = u32::try_from(index / u64::from(self.header.block_size.get()))
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "index out of range"))?;
         let byte_index = index % u64::from(self.header.block_size.get());
@@ -147,11 +270,32 @@ impl Table {
         index: ZIndex,
         ctx: &mut ProbeContext,
     ) -> io::Result<SideValue> {
+        // Positions not listed have DTC 254.
+        Ok(SideValue::Dtc(
+            self.lookup_high_dtc(index, ctx)?.unwrap_or(254),
+        ))
+    }
+
+    /// Looks up a position in a high DTC table. Returns `None` if the
+    /// position is not listed.
+    pub(crate) fn lookup_high_dtc(
+        &self,
+        index: ZIndex,
+        ctx: &mut ProbeContext,
+    ) -> io::Result<Option<i32>> {
         assert_eq!(self.table_type, TableType::HighDtc);
+        self.lookup_high_dtc_entry(index, ctx)
+            .map_err(|error| table_error(&self.path, error))
+    }
 
+    fn lookup_high_dtc_entry(
+        &self,
+        index: ZIndex,
+        ctx: &mut ProbeContext,
+    ) -> io::Result<Option<i32>> {
         let block_index = match self.starting_indices.binary_search(&U64::new(index)) {
             Ok(block_index) => block_index,
-            Err(0) => return Ok(SideValue::Dtc(254)),
+            Err(0) => return Ok(None),
             Err(block_index) => block_index - 1,
         } as u32;
 
@@ -186,21 +330,30 @@ impl Table {
             }
         }
 
-        Ok(SideValue::Dtc(
-            if let Ok(ptr) =
-                decompressed_block.binary_search_by_key(&U64::new(index), |entry| entry.index)
-            {
-                i32::from(decompressed_block[ptr].value)
-            } else {
-                254
-            },
-        ))
+        let Ok(ptr) =
+            decompressed_block.binary_search_by_key(&U64::new(index), |entry| entry.index)
+        else {
+            return Ok(None);
+        };
+
+        let dtc = i32::from(decompressed_block[ptr].value);
+        if dtc < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("negative high dtc {dtc} at index {index}"),
+            ));
+        }
+
+        Ok(Some(dtc))
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub(crate) enum TableType {
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum TableType {
+    /// `.mb` tables with DTC values up to 254.
     Mb,
+    /// `.hi` tables with DTC values that do not fit into `.mb` tables.
     HighDtc,
 }
 
@@ -283,14 +436,14 @@ impl TryFrom<u8> for CompressionMethod {
             0 => CompressionMethod::None,
             1 => {
                 return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
+                    io::ErrorKind::Unsupported,
                     "zlib compression not supported",
                 ));
             }
             2 => CompressionMethod::Zstd,
             _ => {
                 return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
+                    io::ErrorKind::Unsupported,
                     format!("unknown compression method: {value}"),
                 ));
             }
@@ -298,6 +451,9 @@ impl TryFrom<u8> for CompressionMethod {
     }
 }
 
+/// Entry of a `.mb` table. Tables only store wins for white (of the material
+/// of the table), whichever side is to move. So a DTC is a win for the side
+/// to move of the table if that is white, and a loss otherwise.
 #[derive(Debug)]
 pub(crate) enum MbValue {
     Dtc(u8),
@@ -305,16 +461,24 @@ pub(crate) enum MbValue {
     MaybeHighDtc,
 }
 
-#[derive(Debug)]
-pub(crate) enum SideValue {
+/// Entry of a table for one side to move, with high DTC resolved. Like
+/// table entries, this only describes wins for white, so `Dtc` is a loss if
+/// black is to move. `Unresolved` means white does not win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SideValue {
     Dtc(i32),
     Unresolved,
 }
 
+/// Buffers for reading and decompressing table blocks, see
+/// [`Tablebase::probe_with_context()`](crate::Tablebase::probe_with_context).
+/// Create one per thread.
 pub struct ProbeContext {
     compressed_block: Vec<u8>,
     decompressed_block: Vec<u8>,
     decompressor: Decompressor,
+    deadline: Option<Instant>,
+    wdl_only: bool,
 }
 
 impl ProbeContext {
@@ -323,14 +487,223 @@ impl ProbeContext {
             compressed_block: Vec::new(),
             decompressed_block: Vec::new(),
             decompressor: Decompressor::new(),
+            deadline: None,
+            wdl_only: false,
         })
     }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    pub(crate) fn wdl_only(&self) -> bool {
+        self.wdl_only
+    }
+
+    pub(crate) fn set_wdl_only(&mut self) {
+        self.wdl_only = true;
+    }
+}
+
+/// Header and block index at the start of a table.
+struct Index {
+    header: Header,
+    offsets: Box<[U64]>,
+    starting_indices: Box<[U64]>,
+}
+
+/// Reads the header and block index at the start of a table.
+fn read_index(reader: &mut impl Read, table_type: TableType, path: &Path) -> io::Result<Index> {
+    let header = Header::try_from(RawHeader::read_from_io(&mut *reader)?)?;
+
+    if header.list_element_size != table_type.list_element_size() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "unpexected list element size {} for {}",
+                header.list_element_size,
+                path.display(),
+            ),
+        ));
+    }
+
+    if u32::from(header.block_size) % u32::from(table_type.list_element_size()) != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "block size {} not cleanly divisible by list element size",
+                header.block_size
+            ),
+        ));
+    }
+
+    let mut offsets = <[U64]>::new_box_zeroed_with_elems(header.num_blocks as usize + 1)
+        .map_err(|_| out_of_memory("offsets vector"))?;
+    reader.read_exact(offsets.as_mut_bytes())?;
+
+    let starting_indices = match table_type {
+        TableType::Mb => Box::default(),
+        TableType::HighDtc => {
+            let mut starting_indices =
+                <[U64]>::new_box_zeroed_with_elems(header.num_blocks as usize + 1)
+                    .map_err(|_| out_of_memory("starting indices vector"))?;
+            reader.read_exact(starting_indices.as_mut_bytes())?;
+            starting_indices
+        }
+    };
+
+    Ok(Index {
+        header,
+        offsets,
+        starting_indices,
+    })
+}
+
+/// Attaches the path of a table to errors that indicate a broken table, so
+/// that they can be reported as [`TablebaseError`]. The error kind is kept.
+fn table_error(path: &Path, error: io::Error) -> io::Error {
+    if error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<TablebaseError>())
+    {
+        return error;
+    }
+    match error.kind() {
+        io::ErrorKind::Unsupported => io::Error::new(
+            io::ErrorKind::InvalidData,
+            TablebaseError::UnsupportedEncoding {
+                path: path.to_owned(),
+            },
+        ),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => io::Error::new(
+            error.kind(),
+            TablebaseError::CorruptTable {
+                path: path.to_owned(),
+                source: error,
+            },
+        ),
+        _ => error,
+    }
+}
+
+fn out_of_memory(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        format!("failed to allocate {what}"),
+    )
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt as _;
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt as _;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    // Without positional reads, seek and read under a lock, so that
+    // concurrent reads do not move the cursor in between.
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
 }
 
 pub fn fadvise(file: &File, advice: c_int) -> io::Result<()> {
-    if unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) } < 0 {
+    fadvise_range(file, 0, 0, advice)
+}
+
+#[cfg(not(unix))]
+fn fadvise_range(_file: &File, _offset: u64, _len: u64, _advice: c_int) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn fadvise_range(file: &File, offset: u64, len: u64, advice: c_int) -> io::Result<()> {
+    use std::os::fd::AsRawFd as _;
+    let offset = libc::off_t::try_from(offset)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "offset out of range"))?;
+    let len = libc::off_t::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "length out of range"))?;
+    if unsafe { libc::posix_fadvise(file.as_raw_fd(), offset, len, advice) } < 0 {
         Err(io::Error::last_os_error())
     } else {
         Ok(())
     }
 }
+
+#[cfg(not(unix))]
+fn madvise_range(_mmap: &Mmap, _offset: u64, _len: u64, _advice: c_int) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn madvise_range(mmap: &Mmap, offset: u64, len: u64, advice: c_int) -> io::Result<()> {
+    // Only read-ahead has an madvise equivalent. Clamp to the mapping,
+    // because the window may extend past the end of the file.
+    if advice != POSIX_FADV_WILLNEED {
+        return Ok(());
+    }
+    let offset = usize::try_from(offset)
+        .unwrap_or(usize::MAX)
+        .min(mmap.len());
+    let len = usize::try_from(len)
+        .unwrap_or(usize::MAX)
+        .min(mmap.len() - offset);
+    if len == 0 {
+        return Ok(());
+    }
+    mmap.advise_range(memmap2::Advice::WillNeed, offset, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_open_reader_corrupt() {
+        let path = Path::new("kqk_out/kqk_w_0.mb");
+        let open = |bytes: Vec<u8>| {
+            let reader = Box::new(Cursor::new(bytes));
+            TablebaseError::from(
+                Table::open_reader(reader, TableType::Mb, path)
+                    .err()
+                    .unwrap(),
+            )
+        };
+
+        for bytes in [Vec::new(), vec![0; 4096]] {
+            assert!(matches!(
+                open(bytes),
+                TablebaseError::CorruptTable { path: corrupt, .. } if corrupt == path
+            ));
+        }
+        assert!(matches!(
+            open(vec![0xff; 4096]),
+            TablebaseError::UnsupportedEncoding { path: unsupported } if unsupported == path
+        ));
+    }
+}
@@ -1,32 +1,85 @@
 use std::{
+    cmp::{Ordering, Reverse},
+    env,
     ffi::c_int,
-    io,
+    fmt, fs,
+    io::{self, BufRead as _},
     mem::MaybeUninit,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     sync::{
-        Once,
-        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, Once, PoisonError,
+        atomic::{self, AtomicU64, AtomicUsize},
+        mpsc,
     },
+    thread,
+    time::{Duration, Instant},
 };
 
 use mbeval_sys::{
-    BishopParity, MbInfo, PawnFileType, Side, ZIndex, mbeval_get_mb_info, mbeval_init,
+    BishopParity, MAX_PIECES_MB, MbInfo, PawnFileType, Side, ZIndex, mbeval_get_mb_info,
+    mbeval_init,
 };
-use once_cell::sync::OnceCell;
-use rustc_hash::FxHashMap;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use shakmaty::{
-    Board, ByColor, ByRole, CastlingMode, Chess, Color, EnPassantMode, Position as _, Role,
-    fen::Fen,
+    Bitboard, Board, ByColor, ByRole, CastlingMode, Chess, Color, EnPassantMode, FromSetup as _,
+    Move, Position as _, Role, Setup, Square, fen::Fen, san::San,
 };
 
-use crate::table::{MbValue, ProbeContext, SideValue, Table, TableType};
+use crate::{
+    builtin,
+    coverage::{Coverage, SubtableCount, endgame_key, num_pieces, num_possible},
+    error::{ProbeError, TablebaseError},
+    mb_info::{MbInfoSnapshot, num_parities},
+    report::{AddPathReport, VerifyReport},
+    table::{MbValue, ProbeContext, ReadSeek, SideValue, Table, TableType},
+};
 
 const ALL_ONES: ZIndex = !0;
 
 static INIT_MBEVAL: Once = Once::new();
 
+type ReaderFactory = dyn FnMut(&TableKey) -> io::Result<Box<dyn ReadSeek>> + Send;
+
+/// Results of [`Tablebase::probe_stream()`], in the order of the positions.
+#[cfg(feature = "tokio")]
+pub type ProbeStream = tokio::sync::mpsc::Receiver<(Chess, Result<Option<Value>, ProbeError>)>;
+
+/// A collection of tables.
+///
+/// Tables are added through `&mut self`, and then probed through `&self`,
+/// so a `Tablebase` can be shared between threads, e.g. in an [`Arc`].
+/// Tables are opened lazily on first use. Concurrent probes that need the
+/// same table wait for a single thread to open it, except when probing with
+/// a timeout, like [`Tablebase::probe_timeout()`], where the first of the
+/// racing threads to finish wins.
 pub struct Tablebase {
-    tables: FxHashMap<TableKey, (PathBuf, OnceCell<Table>)>,
+    tables: FxHashMap<TableKey, TableEntry>,
+    materials: FxHashSet<Material>,
+    /// Added root paths, and whether they were scanned recursively.
+    paths: Vec<(PathBuf, bool)>,
+    fallback: Option<Box<Tablebase>>,
+    table_type_filter: fn(TableType) -> bool,
+    dedup_inodes: bool,
+    inodes: FxHashSet<(u64, u64)>,
+    role_values: ByRole<usize>,
+    ignore_castling_rights: bool,
+    tie_break: TieBreak,
+    evict_on_oom: bool,
+    max_open: Option<usize>,
+    read_ahead: u32,
+    mmap: bool,
+    ep_mode: EnPassantMode,
+    corrupt_policy: CorruptPolicy,
+    wdl_without_high_dtc: bool,
+    builtin_solver: bool,
+    prefer_high_dtc: bool,
+    reader_factory: Option<Mutex<Box<ReaderFactory>>>,
+    clock: AtomicU64,
     stats: Stats,
 }
 
@@ -37,6 +90,12 @@ impl Default for Tablebase {
 }
 
 impl Tablebase {
+    /// Creates an empty tablebase. Add tables with [`Tablebase::add_path()`].
+    ///
+    /// The first call in a process also initializes mbeval. This only
+    /// computes read-only lookup tables for indexing, so it takes no options
+    /// and is shared by all instances. Tables and settings are per instance:
+    /// paths added to one tablebase are never visible to another.
     pub fn new() -> Tablebase {
         INIT_MBEVAL.call_once(|| {
             unsafe {
@@ -47,267 +106,2424 @@ impl Tablebase {
 
         Tablebase {
             tables: FxHashMap::default(),
+            materials: FxHashSet::default(),
+            paths: Vec::new(),
+            fallback: None,
+            table_type_filter: |_| true,
+            dedup_inodes: false,
+            inodes: FxHashSet::default(),
+            role_values: DEFAULT_ROLE_VALUES,
+            ignore_castling_rights: false,
+            tie_break: TieBreak::default(),
+            evict_on_oom: false,
+            max_open: None,
+            read_ahead: 0,
+            mmap: false,
+            ep_mode: EnPassantMode::Legal,
+            corrupt_policy: CorruptPolicy::default(),
+            wdl_without_high_dtc: false,
+            builtin_solver: false,
+            prefer_high_dtc: false,
+            reader_factory: None,
+            clock: AtomicU64::new(0),
             stats: Stats::default(),
         }
     }
 
-    pub fn add_path(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+    /// Creates an empty tablebase that keeps at most `max_open` tables open.
+    /// When opening another table, the least recently used tables are
+    /// closed, as soon as concurrent probes are done with them. They are
+    /// reopened when needed again.
+    pub fn with_capacity(max_open: usize) -> Tablebase {
+        Tablebase {
+            max_open: Some(max_open),
+            ..Tablebase::new()
+        }
+    }
+
+    /// Consults `fallback` whenever a table required to probe a position is
+    /// missing. Positions that no table could answer are not forwarded.
+    /// Fallbacks are tried in the order they were added.
+    pub fn with_fallback(mut self, fallback: Tablebase) -> Tablebase {
+        self.fallback = Some(Box::new(match self.fallback.take() {
+            Some(existing) => (*existing).with_fallback(fallback),
+            None => fallback,
+        }));
+        self
+    }
+
+    /// Reads tables through readers returned by `factory` instead of opening
+    /// the added paths, for example to serve tables from memory or custom
+    /// storage. Tables are still added with [`Tablebase::add_path()`] or
+    /// [`Tablebase::load_index()`], which do not require the files to
+    /// exist, see also [`TableKey::from_path()`]. Timeouts do not apply to
+    /// readers.
+    pub fn with_reader_factory(
+        mut self,
+        factory: impl FnMut(&TableKey) -> io::Result<Box<dyn ReadSeek>> + Send + 'static,
+    ) -> Tablebase {
+        self.reader_factory = Some(Mutex::new(Box::new(factory)));
+        self
+    }
+
+    /// Only add tables of types for which `filter` returns `true`, e.g.
+    /// `|table_type| table_type == TableType::Mb` to skip high DTC tables.
+    /// Affects subsequent calls to [`Tablebase::add_path()`].
+    pub fn set_table_type_filter(&mut self, filter: fn(TableType) -> bool) {
+        self.table_type_filter = filter;
+    }
+
+    /// Resolve symlinks when adding paths, and register each physical table
+    /// file (identified by device and inode) only once, even if it is
+    /// reachable through multiple links. Disabled by default. Only supported
+    /// on Unix.
+    pub fn set_dedup_inodes(&mut self, dedup_inodes: bool) {
+        self.dedup_inodes = dedup_inodes;
+    }
+
+    /// Sets the piece values used to decide which side is stronger. The
+    /// stronger side is probed first, so values that better predict the
+    /// winning side in the installed endgames save probes of the flipped
+    /// position (counted as false predictions in [`Tablebase::stats()`]).
+    /// Results do not depend on the values.
+    ///
+    /// Defaults to 1, 3, 3, 5, 9 for pawns, knights, bishops, rooks, and
+    /// queens. The value of kings is irrelevant.
+    pub fn set_role_values(&mut self, role_values: ByRole<usize>) {
+        self.role_values = role_values;
+    }
+
+    /// Probe positions with castling rights as if they had none, e.g. for
+    /// endgames reached in Chess960 games, where the rights often linger.
+    /// Disabled by default, because results are wrong if castling would
+    /// actually make a difference.
+    pub fn set_ignore_castling_rights(&mut self, ignore_castling_rights: bool) {
+        self.ignore_castling_rights = ignore_castling_rights;
+    }
+
+    /// Chooses among equally good moves in [`Tablebase::best_move()`] and
+    /// everything based on it.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = tie_break;
+    }
+
+    /// When opening a table fails for lack of memory, close the least
+    /// recently used half of the open tables and retry once. Tables that are
+    /// still in use by concurrent probes are released as soon as those
+    /// finish. Disabled by default.
+    pub fn set_evict_on_oom(&mut self, evict_on_oom: bool) {
+        self.evict_on_oom = evict_on_oom;
+    }
+
+    /// When consecutive reads from a table hit adjacent blocks, as is common
+    /// in [`Tablebase::principal_variation()`] and full table scans, ask the
+    /// kernel to prefetch the following `blocks` blocks. Isolated random
+    /// probes are unaffected. Disabled (0) by default. Affects tables opened
+    /// afterwards.
+    pub fn set_read_ahead(&mut self, blocks: u32) {
+        self.read_ahead = blocks;
+    }
+
+    /// Memory-map table files instead of reading blocks with system calls,
+    /// which lowers the latency of each probe, at the cost of address space.
+    /// Disabled by default. Affects tables opened afterwards, but not those
+    /// provided by [`Tablebase::with_reader_factory()`].
+    pub fn set_mmap(&mut self, mmap: bool) {
+        self.mmap = mmap;
+    }
+
+    /// Selects which en passant squares of a position are passed on to the
+    /// tables. Defaults to [`EnPassantMode::Legal`]. For example,
+    /// [`EnPassantMode::Always`] keeps the square of every double pawn push,
+    /// matching positions validated with that mode.
+    ///
+    /// The square is extracted once, before deciding which side to probe
+    /// first, and the mirrored position keeps the mirrored square, so both
+    /// sides are probed consistently.
+    pub fn set_en_passant_mode(&mut self, ep_mode: EnPassantMode) {
+        self.ep_mode = ep_mode;
+    }
+
+    /// Decides what happens when probing hits a corrupt or truncated table.
+    pub fn set_corrupt_policy(&mut self, corrupt_policy: CorruptPolicy) {
+        self.corrupt_policy = corrupt_policy;
+    }
+
+    /// Lets [`Tablebase::probe_wdl()`] answer positions with high DTC even
+    /// if the `.hi` table is not installed: the `.mb` table already tells
+    /// that the position is decisive, only the distance is unavailable.
+    /// Disabled by default. DTC probes are not affected.
+    pub fn set_wdl_without_high_dtc(&mut self, wdl_without_high_dtc: bool) {
+        self.wdl_without_high_dtc = wdl_without_high_dtc;
+    }
+
+    /// Answer KQvK and KRvK positions with a builtin solver instead of
+    /// tables, with identical results. The solution is computed in memory
+    /// on first use, which takes a moment. Disabled by default.
+    pub fn set_builtin_solver(&mut self, builtin_solver: bool) {
+        self.builtin_solver = builtin_solver;
+    }
+
+    /// Consult the `.hi` table, if installed, whenever the `.mb` table
+    /// stores the largest value it can represent (254), even if the header
+    /// of the `.mb` table claims that no higher DTC exists. Lower values are
+    /// still answered by the `.mb` table alone. Disabled by default.
+    pub fn set_prefer_high_dtc(&mut self, prefer_high_dtc: bool) {
+        self.prefer_high_dtc = prefer_high_dtc;
+    }
+
+    /// Adds tables from subdirectories like `kqk_out` of `path`.
+    ///
+    /// Fails only if `path` itself cannot be read, or with
+    /// [`TablebaseError::InvalidPath`] if it is not a directory. Unreadable
+    /// subdirectories and files are skipped with a warning. Returns the
+    /// number of table files added.
+    pub fn add_path(&mut self, path: impl AsRef<Path>) -> Result<usize, TablebaseError> {
+        Ok(self.add_path_report(path)?.tables())
+    }
+
+    /// Like [`Tablebase::add_path()`], but returns a summary of what was
+    /// added and skipped, e.g. to show to users.
+    pub fn add_path_report(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<AddPathReport, TablebaseError> {
+        self.add_root(path.as_ref(), false)
+    }
+
+    /// Like [`Tablebase::add_path_report()`], but also finds table
+    /// directories like `kqk_out` nested at any depth below `path`, e.g. in
+    /// subdirectories by number of pieces. Symlinks are only followed if
+    /// they point to table directories.
+    pub fn add_path_recursive(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<AddPathReport, TablebaseError> {
+        self.add_root(path.as_ref(), true)
+    }
+
+    /// Like [`Tablebase::add_path_report()`], but lists the table
+    /// directories of `path` in parallel, which speeds up startup with many
+    /// tables on slow storage.
+    ///
+    /// This is only about discovering table files. Tables are still opened
+    /// lazily when first probed, and probing is thread-safe regardless of
+    /// how tables were added.
+    #[cfg(feature = "rayon")]
+    pub fn add_path_parallel(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<AddPathReport, TablebaseError> {
+        let path = path.as_ref();
+        let directories =
+            find_table_directories(path, false).map_err(|error| path_error(path, error))?;
+        let listed: Vec<_> = directories
+            .into_par_iter()
+            .map(|directory| {
+                let files = directory.read_files();
+                (directory, files)
+            })
+            .collect();
+        let report = self.add_listed(path, listed, true);
+        self.remember_path(path, false);
+        Ok(report)
+    }
+
+    fn add_root(&mut self, path: &Path, recursive: bool) -> Result<AddPathReport, TablebaseError> {
+        let report = self.scan_path(path, true, recursive)?;
+        self.remember_path(path, recursive);
+        Ok(report)
+    }
+
+    /// Remembers `path` for [`Tablebase::reload_changed()`].
+    fn remember_path(&mut self, path: &Path, recursive: bool) {
+        match self.paths.iter_mut().find(|(added, _)| added == path) {
+            Some((_, added_recursive)) => *added_recursive |= recursive,
+            None => self.paths.push((path.to_owned(), recursive)),
+        }
+    }
+
+    /// Scans the paths previously added with [`Tablebase::add_path()`]
+    /// again, and adds tables that were not yet known, e.g. while a
+    /// download is still in progress. Already added tables are kept as they
+    /// are, including open files. Paths that can no longer be read are
+    /// skipped with a warning. Returns the number of newly added table
+    /// files.
+    pub fn reload_changed(&mut self) -> usize {
         let mut num = 0;
-        for directory in path.as_ref().read_dir()? {
-            let directory = directory?.path();
-            if let Some((dir_material, pawn_file_type, bishop_parity)) = parse_dirname(&directory) {
-                for file in directory.read_dir()? {
-                    let file = file?.path();
-                    if let Some((file_material, side, kk_index, table_type)) = parse_filename(&file)
-                    {
-                        if dir_material == file_material {
-                            self.tables.insert(
-                                TableKey {
-                                    material: file_material,
-                                    pawn_file_type,
-                                    bishop_parity,
-                                    side,
-                                    kk_index,
-                                    table_type,
-                                },
-                                (file, OnceCell::new()),
-                            );
-                            num += 1;
-                        }
-                    }
+        for (path, recursive) in self.paths.clone() {
+            match self.scan_path(&path, false, recursive) {
+                Ok(report) => num += report.tables(),
+                Err(error) => tracing::warn!(%error, "skipping unreadable {}", path.display()),
+            }
+        }
+        num
+    }
+
+    /// Adds tables from subdirectories of `path`. Tables that were already
+    /// added are replaced only if `replace` is set.
+    fn scan_path(
+        &mut self,
+        path: &Path,
+        replace: bool,
+        recursive: bool,
+    ) -> Result<AddPathReport, TablebaseError> {
+        let directories =
+            find_table_directories(path, recursive).map_err(|error| path_error(path, error))?;
+        let listed = directories.into_iter().map(|directory| {
+            let files = directory.read_files();
+            (directory, files)
+        });
+        Ok(self.add_listed(path, listed, replace))
+    }
+
+    /// Adds the listed files of table directories found below `path`.
+    fn add_listed(
+        &mut self,
+        path: &Path,
+        listed: impl IntoIterator<Item = (TableDirectory, io::Result<Vec<Option<PathBuf>>>)>,
+        replace: bool,
+    ) -> AddPathReport {
+        let mut report = AddPathReport::new(path.to_owned());
+        let mut materials = FxHashSet::default();
+        for (directory, files) in listed {
+            let files = match files {
+                Ok(files) => files,
+                Err(error) => {
+                    tracing::warn!(%error, "skipping unreadable {}", directory.path.display());
+                    continue;
                 }
+            };
+            for file in files {
+                let added = file.and_then(|file| {
+                    self.add_file(
+                        file,
+                        directory.material,
+                        directory.pawn_file_type,
+                        directory.bishop_parity,
+                        replace,
+                    )
+                });
+                if added.is_some() {
+                    materials.insert(directory.material);
+                }
+                report.record(added);
             }
         }
-        tracing::info!("added {num} table files");
-        Ok(num)
+        report.materials = materials.len();
+        if report.tables() > 0 {
+            tracing::info!("added {} table files", report.tables());
+        } else if replace {
+            tracing::warn!(
+                "no table files found in {}, expected subdirectories like kqk_out",
+                path.display()
+            );
+        }
+        report
     }
 
-    fn open_table(&self, key: &TableKey) -> io::Result<Option<&Table>> {
-        self.tables
-            .get(key)
-            .map(|(path, table)| table.get_or_try_init(|| Table::open(path, key.table_type)))
-            .transpose()
+    /// Adds tables from each directory in a list separated like `PATH`
+    /// (`:` on Unix, `;` on Windows), as accepted by
+    /// [`Tablebase::add_path()`]. Empty entries are ignored.
+    ///
+    /// Does not stop at unreadable directories. Returns the total number of
+    /// table files added, and the directories that could not be read.
+    pub fn add_path_list(&mut self, list: &str) -> (usize, Vec<(PathBuf, TablebaseError)>) {
+        let mut num = 0;
+        let mut errors = Vec::new();
+        for path in env::split_paths(list) {
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            match self.add_path(&path) {
+                Ok(n) => num += n,
+                Err(error) => errors.push((path, error)),
+            }
+        }
+        (num, errors)
     }
 
-    fn select_table(
-        &self,
-        pos: &Chess,
-        mb_info: &MbInfo,
-        table_type: TableType,
-    ) -> io::Result<Option<(&Table, ZIndex)>> {
-        let table_key = TableKey {
-            material: pos.board().material(),
-            pawn_file_type: PawnFileType::Free,
-            bishop_parity: ByColor::new_with(|_| BishopParity::None),
-            side: pos.turn(),
-            kk_index: KkIndex(mb_info.kk_index as u32),
+    /// Adds a single table file, if its name matches the material of its
+    /// directory and it passes the configured filters. An existing table with
+    /// the same key is replaced only if `replace` is set. Returns the type of
+    /// the added table.
+    fn add_file(
+        &mut self,
+        file: PathBuf,
+        dir_material: Material,
+        pawn_file_type: PawnFileType,
+        bishop_parity: ByColor<BishopParity>,
+        replace: bool,
+    ) -> Option<TableType> {
+        let (file_material, side, kk_index, table_type) = parse_filename(&file)?;
+        if dir_material != file_material || !(self.table_type_filter)(table_type) {
+            return None;
+        }
+        let key = TableKey {
+            material: file_material,
+            pawn_file_type,
+            bishop_parity,
+            side,
+            kk_index,
             table_type,
         };
+        if !replace && self.tables.contains_key(&key) {
+            return None;
+        }
+        if self.dedup_inodes {
+            let metadata = match fs::metadata(&file) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    tracing::warn!(%error, "skipping unreadable {}", file.display());
+                    return None;
+                }
+            };
+            if file_id(&metadata).is_some_and(|id| !self.inodes.insert(id)) {
+                tracing::debug!("skipping duplicate link {}", file.display());
+                return None;
+            }
+        }
+        self.materials.insert(file_material);
+        self.tables.insert(key, TableEntry::new(file));
+        Some(table_type)
+    }
 
-        for bishop_parity in &mb_info.parity_index[..mb_info.num_parities as usize] {
-            if let Some(table) = self.open_table(&TableKey {
-                bishop_parity: ByColor {
-                    white: bishop_parity.bishop_parity[Side::White as usize],
-                    black: bishop_parity.bishop_parity[Side::Black as usize],
-                },
-                ..table_key
-            })? {
-                return Ok(Some((table, bishop_parity.index)));
+    /// Writes the paths of all added tables to `path`, one per line, so that
+    /// they can later be added with [`Tablebase::load_index()`] instead of
+    /// scanning directories. Fallbacks are not included.
+    pub fn save_index(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut files: Vec<&str> = Vec::with_capacity(self.tables.len());
+        for entry in self.tables.values() {
+            match entry.path.to_str() {
+                Some(file) => files.push(file),
+                None => tracing::warn!("cannot save non UTF-8 path {}", entry.path.display()),
             }
         }
+        files.sort_unstable();
 
-        let index = match mb_info.pawn_file_type {
-            PawnFileType::Free => ALL_ONES,
-            PawnFileType::Bp11 => {
-                if mb_info.index_op_11 != ALL_ONES {
-                    if let Some(table) = self.open_table(&TableKey {
-                        pawn_file_type: PawnFileType::Op11,
-                        ..table_key
-                    })? {
-                        return Ok(Some((table, mb_info.index_op_11)));
-                    }
-                }
-                mb_info.index_bp_11
-            }
-            PawnFileType::Op11 => mb_info.index_op_11,
-            PawnFileType::Op21 => mb_info.index_op_21,
-            PawnFileType::Op12 => mb_info.index_op_12,
-            PawnFileType::Op22 => mb_info.index_op_22,
-            PawnFileType::Dp22 => {
-                if mb_info.index_op_22 != ALL_ONES {
-                    if let Some(table) = self.open_table(&TableKey {
-                        pawn_file_type: PawnFileType::Op22,
-                        ..table_key
-                    })? {
-                        return Ok(Some((table, mb_info.index_op_22)));
-                    }
-                }
-                mb_info.index_dp_22
-            }
-            PawnFileType::Op31 => mb_info.index_op_31,
-            PawnFileType::Op13 => mb_info.index_op_13,
-            PawnFileType::Op41 => mb_info.index_op_41,
-            PawnFileType::Op14 => mb_info.index_op_14,
-            PawnFileType::Op32 => mb_info.index_op_32,
-            PawnFileType::Op23 => mb_info.index_op_23,
-            PawnFileType::Op33 => mb_info.index_op_33,
-            PawnFileType::Op42 => mb_info.index_op_42,
-            PawnFileType::Op24 => mb_info.index_op_24,
+        let mut index = String::new();
+        for file in files {
+            index.push_str(file);
+            index.push('\n');
+        }
+        fs::write(path, index)
+    }
+
+    /// Adds the tables listed in an index written by
+    /// [`Tablebase::save_index()`], without scanning directories.
+    ///
+    /// Entries outside of table directories like `kqk_out` are skipped with
+    /// a warning.
+    /// Files are not checked for existence, so tables removed since the index
+    /// was written only fail when probed. Returns the number of table files
+    /// added.
+    pub fn load_index(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let path = path.as_ref();
+        let mut num = 0;
+        for line in fs::read_to_string(path)?.lines() {
+            if line.is_empty() {
+                continue;
+            }
+            let file = PathBuf::from(line);
+            let Some((material, pawn_file_type, bishop_parity)) =
+                file.parent().and_then(parse_dirname)
+            else {
+                tracing::warn!("skipping invalid entry {line} in {}", path.display());
+                continue;
+            };
+            if self
+                .add_file(file, material, pawn_file_type, bishop_parity, true)
+                .is_some()
+            {
+                num += 1;
+            }
+        }
+        tracing::info!("added {num} table files from {}", path.display());
+        Ok(num)
+    }
+
+    /// Dumps the keys and paths of all added tables as a JSON array, sorted
+    /// by path, for inspection with external tools. Fallbacks are not
+    /// included.
+    #[cfg(feature = "serde")]
+    pub fn index_to_json(&self) -> String {
+        let mut entries: Vec<IndexEntry> = self
+            .tables
+            .iter()
+            .map(|(key, entry)| IndexEntry {
+                material: material_name(&key.material),
+                pawn_file_type: format!("{:?}", key.pawn_file_type),
+                bishop_parity: [
+                    format!("{:?}", key.bishop_parity.white),
+                    format!("{:?}", key.bishop_parity.black),
+                ],
+                side: key.side.char(),
+                kk_index: key.kk_index.0,
+                table_type: key.table_type,
+                path: entry.path.to_string_lossy().into_owned(),
+            })
+            .collect();
+        entries.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        serde_json::to_string_pretty(&entries).expect("serialize index")
+    }
+
+    /// Distinct materials of all added tables, ordered by number of pieces
+    /// and then by [`material_string()`]. Fallbacks are not included.
+    pub fn materials(&self) -> Vec<Material> {
+        let mut materials: Vec<Material> = self.materials.iter().copied().collect();
+        materials.sort_by_cached_key(|material| {
+            (num_pieces(&endgame_key(material)), material_name(material))
+        });
+        materials
+    }
+
+    /// Checks if any table with exactly this material (white pieces, black
+    /// pieces) has been added.
+    pub fn contains_material(&self, material: &Material) -> bool {
+        self.materials.contains(material)
+    }
+
+    /// Checks that tables for all of `materials` have been added, e.g. to
+    /// fail fast at startup. Returns the missing materials otherwise.
+    pub fn assert_loaded(&self, materials: &[Material]) -> Result<(), Vec<Material>> {
+        let missing: Vec<Material> = materials
+            .iter()
+            .filter(|material| !self.contains_material(material))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Checks that positions with `material` can be answered end to end, by
+    /// building a sample position and probing it. Unlike
+    /// [`Tablebase::contains_material()`], this catches tables that are
+    /// present, but cannot actually be read. Materials that need no table,
+    /// like KBvK, are loadable.
+    ///
+    /// Returns `None` if no legal position with this material could be
+    /// built.
+    pub fn validate_material_loadable(
+        &self,
+        material: &Material,
+    ) -> Result<Option<bool>, ProbeError> {
+        let Some(pos) = sample_position(material) else {
+            return Ok(None);
         };
+        Ok(Some(!matches!(
+            self.probe_detailed(&pos)?,
+            ProbeOutcome::OutOfScope
+                | ProbeOutcome::TableMissing { .. }
+                | ProbeOutcome::MbevalRejected { .. }
+        )))
+    }
+
+    /// Iterates over all added tables, in arbitrary order. Fallbacks are not
+    /// included.
+    pub fn keys(&self) -> impl Iterator<Item = &TableKey> {
+        self.tables.keys()
+    }
+
+    /// Counts the added sub-tables for `material`, to compare with
+    /// [`expected_subtable_count()`](crate::expected_subtable_count).
+    pub fn loaded_subtable_count(&self, material: &Material) -> SubtableCount {
+        let mut directories = FxHashSet::default();
+        let mut count = SubtableCount::default();
+        for key in self.tables.keys().filter(|key| key.material == *material) {
+            directories.insert((key.pawn_file_type, key.bishop_parity));
+            match key.table_type {
+                TableType::Mb => count.mb_files += 1,
+                TableType::HighDtc => count.high_dtc_files += 1,
+            }
+        }
+        count.directories = directories.len();
+        count
+    }
+
+    /// Compares the added tables with those of `other`, e.g. to verify a
+    /// mirror. Only table keys are compared, not contents, see
+    /// [`Tablebase::diff_contents()`]. Fallbacks are not included.
+    pub fn diff(&self, other: &Tablebase) -> TablebaseDiff {
+        TablebaseDiff {
+            only_in_self: self
+                .keys()
+                .filter(|key| !other.tables.contains_key(key))
+                .copied()
+                .collect(),
+            only_in_other: other
+                .keys()
+                .filter(|key| !self.tables.contains_key(key))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Compares the files of all tables added to both `self` and `other`
+    /// byte by byte, and returns the keys of those that differ. Reads all
+    /// common tables completely, so this is expensive.
+    pub fn diff_contents(&self, other: &Tablebase) -> io::Result<Vec<TableKey>> {
+        let mut mismatches = Vec::new();
+        for (key, entry) in &self.tables {
+            let Some(other_entry) = other.tables.get(key) else {
+                continue;
+            };
+            if !files_equal(&entry.path, &other_entry.path)? {
+                mismatches.push(*key);
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Summarizes the loaded endgames by number of pieces, for each number
+    /// of pieces from 3 up to the largest loaded endgame.
+    pub fn coverage(&self) -> Vec<Coverage> {
+        let endgames: FxHashSet<_> = self.materials.iter().map(endgame_key).collect();
+        let max_pieces = endgames.iter().map(num_pieces).max().unwrap_or(0);
+        (3..=max_pieces)
+            .map(|pieces| Coverage {
+                pieces,
+                loaded: endgames
+                    .iter()
+                    .filter(|endgame| num_pieces(endgame) == pieces)
+                    .count(),
+                possible: num_possible(pieces),
+            })
+            .collect()
+    }
 
-        if index == ALL_ONES {
+    /// Returns the maximum DTC over all added tables, as stored in their
+    /// headers. Reads the header of each table that is not yet open.
+    pub fn max_dtc(&self) -> Result<Option<u32>, TablebaseError> {
+        Ok(self.max_dtc_by(|_| true)?)
+    }
+
+    /// Like [`Tablebase::max_dtc()`], but only considers tables with the given
+    /// material.
+    pub fn max_dtc_for_material(&self, material: &Material) -> Result<Option<u32>, TablebaseError> {
+        Ok(self.max_dtc_by(|key| key.material == *material)?)
+    }
+
+    fn max_dtc_by(&self, filter: impl Fn(&TableKey) -> bool) -> io::Result<Option<u32>> {
+        let mut max_dtc = None;
+        for (key, entry) in &self.tables {
+            if filter(key) {
+                let open = entry.lock().as_ref().map(|table| table.max_dtc());
+                let dtc = match open {
+                    Some(dtc) => dtc,
+                    None => match self.open_reader(key)? {
+                        Some(reader) => Table::read_max_dtc(reader)?,
+                        None => Table::read_max_dtc(fs::File::open(&entry.path)?)?,
+                    },
+                };
+                max_dtc = max_dtc.max(Some(dtc));
+            }
+        }
+        Ok(max_dtc)
+    }
+
+    fn open_table(
+        &self,
+        key: &TableKey,
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<Arc<Table>>> {
+        let Some(entry) = self.tables.get(key) else {
             return Ok(None);
+        };
+        let now = self.clock.fetch_add(1, atomic::Ordering::Relaxed);
+        entry.last_used.store(now, atomic::Ordering::Relaxed);
+
+        if deadline.is_none() {
+            // Hold the lock while opening, so that concurrent probes do not
+            // open the same table twice.
+            let mut table = entry.lock();
+            if let Some(table) = &*table {
+                return Ok(Some(Arc::clone(table)));
+            }
+            let opened = Arc::new(self.open_file(key, entry, None)?);
+            *table = Some(Arc::clone(&opened));
+            drop(table);
+            self.enforce_max_open(key);
+            return Ok(Some(opened));
         }
 
-        Ok(self
-            .open_table(&TableKey {
-                pawn_file_type: mb_info.pawn_file_type,
-                ..table_key
-            })?
-            .map(|table| (table, index)))
+        if let Some(table) = &*entry.lock() {
+            return Ok(Some(Arc::clone(table)));
+        }
+        let opened = self.open_file(key, entry, deadline)?;
+        let opened = Arc::clone(entry.lock().get_or_insert_with(|| Arc::new(opened)));
+        self.enforce_max_open(key);
+        Ok(Some(opened))
+    }
+
+    /// Closes least recently used tables beyond the capacity configured with
+    /// [`Tablebase::with_capacity()`], except the just opened `keep`.
+    fn enforce_max_open(&self, keep: &TableKey) {
+        if let Some(max_open) = self.max_open {
+            let evicted = self.evict_lru(keep, Some(max_open));
+            tracing::trace!("evicted {evicted} tables to stay within capacity");
+        }
+    }
+
+    fn open_file(
+        &self,
+        key: &TableKey,
+        entry: &TableEntry,
+        deadline: Option<Instant>,
+    ) -> io::Result<Table> {
+        let path = &entry.path;
+        let open = || match self.open_reader(key)? {
+            Some(reader) => Table::open_reader(reader, key.table_type, path),
+            None => open_with_deadline(path, key.table_type, self.mmap, deadline, &entry.pending),
+        };
+        let table = match open() {
+            Err(error) if error.kind() == io::ErrorKind::OutOfMemory && self.evict_on_oom => {
+                let evicted = self.evict_lru(key, None);
+                tracing::warn!(%error, "evicted {evicted} tables to open {}", path.display());
+                open()
+            }
+            result => result,
+        }?;
+        Ok(table.with_read_ahead(self.read_ahead))
+    }
+
+    /// Gets a reader for `key` from the reader factory, if any.
+    fn open_reader(&self, key: &TableKey) -> io::Result<Option<Box<dyn ReadSeek>>> {
+        let Some(factory) = &self.reader_factory else {
+            return Ok(None);
+        };
+        let mut factory = factory.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(Some(factory(key)?))
+    }
+
+    /// Closes the least recently used half of the open tables, or as many as
+    /// needed to keep at most `max_open` tables open, except `keep` (which
+    /// counts as open). Tables that are currently being opened are skipped.
+    /// Returns the number of closed tables.
+    fn evict_lru(&self, keep: &TableKey, max_open: Option<usize>) -> usize {
+        let mut open: Vec<(u64, &TableEntry)> = self
+            .tables
+            .iter()
+            .filter(|&(key, entry)| key != keep && entry.is_open())
+            .map(|(_, entry)| (entry.last_used.load(atomic::Ordering::Relaxed), entry))
+            .collect();
+        open.sort_unstable_by_key(|&(last_used, _)| last_used);
+
+        let num = match max_open {
+            Some(max_open) => (open.len() + 1).saturating_sub(max_open),
+            None => open.len().div_ceil(2),
+        };
+        open.iter()
+            .take(num)
+            .filter(|(_, entry)| {
+                entry
+                    .table
+                    .try_lock()
+                    .is_ok_and(|mut table| table.take().is_some())
+            })
+            .count()
+    }
+
+    fn select_table(
+        &self,
+        turn: Color,
+        material: Material,
+        mb_info: &MbInfo,
+        table_type: TableType,
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<(Arc<Table>, ZIndex)>> {
+        select(
+            table_key(turn, material, mb_info, table_type),
+            mb_info,
+            |key| self.open_table(key, deadline),
+        )
     }
 
     fn probe_side(
         &self,
-        pos: &Chess,
+        pos: &Setup,
+        material: Material,
         ctx: &mut ProbeContext,
     ) -> Result<Option<SideValue>, io::Error> {
         // If one side has no pieces, only the other side can potentially win.
-        if !pos.board().white().more_than_one() {
+        // Tables store wins for white, so a lone white king never wins, and
+        // wins against it are found when probing the mirrored position.
+        if !pos.board.white().more_than_one() {
             return Ok(Some(SideValue::Unresolved));
         }
 
-        // Retrieve MB_INFO struct.
-        let mut squares = [mbeval_sys::Piece::NO_PIECE; 64];
-        for (sq, piece) in pos.board() {
-            let role = match piece.role {
-                Role::Pawn => mbeval_sys::Piece::PAWN,
-                Role::Knight => mbeval_sys::Piece::KNIGHT,
-                Role::Bishop => mbeval_sys::Piece::BISHOP,
-                Role::Rook => mbeval_sys::Piece::ROOK,
-                Role::Queen => mbeval_sys::Piece::QUEEN,
-                Role::King => mbeval_sys::Piece::KING,
-            };
-            squares[usize::from(sq)] = piece.color.fold_wb(role, -role);
-        }
-        let mut mb_info: MaybeUninit<MbInfo> = MaybeUninit::zeroed();
-        let result = unsafe {
-            mbeval_get_mb_info(
-                squares.as_ptr(),
-                pos.turn().fold_wb(Side::White, Side::Black),
-                pos.ep_square(EnPassantMode::Legal).map_or(0, c_int::from),
-                mb_info.as_mut_ptr(),
-            )
+        let Some(mb_info) = get_mb_info(pos) else {
+            return Ok(None);
+        };
+
+        self.probe_mb_info(pos.turn, material, &mb_info, ctx)
+    }
+
+    /// Reads the value of a position, given by its MB_INFO, from the tables
+    /// for `material`.
+    fn probe_mb_info(
+        &self,
+        turn: Color,
+        material: Material,
+        mb_info: &MbInfo,
+        ctx: &mut ProbeContext,
+    ) -> Result<Option<SideValue>, io::Error> {
+        let deadline = ctx.deadline();
+
+        let Some((table, index)) =
+            self.select_table(turn, material, mb_info, TableType::Mb, deadline)?
+        else {
+            // Without a .mb table, a standalone .hi table can still answer
+            // positions with high DTC.
+            return Ok(
+                match self.select_table(turn, material, mb_info, TableType::HighDtc, deadline)? {
+                    Some((table, index)) => table.lookup_high_dtc(index, ctx)?.map(SideValue::Dtc),
+                    None => None,
+                },
+            );
         };
-        if result != 0 {
+
+        Ok(match table.read_mb(index, ctx)? {
+            MbValue::Dtc(254) if self.prefer_high_dtc => {
+                match self.select_table(turn, material, mb_info, TableType::HighDtc, deadline)? {
+                    Some((table, index)) => Some(table.read_high_dtc(index, ctx)?),
+                    None => Some(SideValue::Dtc(254)),
+                }
+            }
+            MbValue::Dtc(dtc) => Some(SideValue::Dtc(i32::from(dtc))),
+            MbValue::Unresolved => Some(SideValue::Unresolved),
+            MbValue::MaybeHighDtc => {
+                match self.select_table(turn, material, mb_info, TableType::HighDtc, deadline)? {
+                    Some((table, index)) => Some(table.read_high_dtc(index, ctx)?),
+                    // At least 254, which is all that matters for WDL.
+                    None if ctx.wdl_only() => Some(SideValue::Dtc(254)),
+                    None => None,
+                }
+            }
+        })
+    }
+
+    /// Low-level entry point for testing tools like fuzzers: probes a
+    /// position given in the square encoding of mbeval (indexed by
+    /// [`Square`], with `mbeval_sys::Piece` values, negative for black),
+    /// skipping all validation and the choice of side.
+    ///
+    /// Only the tables for the given material are consulted, so the result
+    /// is a win for white or `Unresolved`. Returns `None` if mbeval rejects
+    /// the position or the table is missing. Not intended for normal use,
+    /// see [`Tablebase::probe()`] instead.
+    pub fn probe_squares(
+        &self,
+        squares: &[c_int; 64],
+        turn: Color,
+        ep_square: Option<Square>,
+    ) -> Result<Option<SideValue>, ProbeError> {
+        let mut material = Material::default();
+        for (sq, &code) in Square::ALL.into_iter().zip(squares) {
+            let role = match code.saturating_abs() {
+                0 => continue,
+                c if c == mbeval_sys::Piece::PAWN.0 => Role::Pawn,
+                c if c == mbeval_sys::Piece::KNIGHT.0 => Role::Knight,
+                c if c == mbeval_sys::Piece::BISHOP.0 => Role::Bishop,
+                c if c == mbeval_sys::Piece::ROOK.0 => Role::Rook,
+                c if c == mbeval_sys::Piece::QUEEN.0 => Role::Queen,
+                c if c == mbeval_sys::Piece::KING.0 => Role::King,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid piece code {code} on {sq}"),
+                    )
+                    .into());
+                }
+            };
+            material[Color::from_white(code > 0)][role] += 1;
+        }
+        let Some(mb_info) = mb_info_from_squares(&squares.map(mbeval_sys::Piece), turn, ep_square)
+        else {
             return Ok(None);
+        };
+        Ok(self.probe_mb_info(turn, material, &mb_info, &mut ProbeContext::new()?)?)
+    }
+
+    /// Returns the position as probed first by [`Tablebase::probe()`]:
+    /// mirrored with colors swapped if black is stronger according to the
+    /// role values (see [`Tablebase::set_role_values()`]), or unchanged,
+    /// including if both sides are equally strong. The result of probing it
+    /// is the same.
+    pub fn canonical(&self, pos: &Chess) -> Chess {
+        if !needs_flip(pos.board(), &self.role_values) {
+            return pos.clone();
+        }
+        let mode = pos.castles().mode();
+        Chess::from_setup(
+            pos.clone()
+                .into_setup(EnPassantMode::Always)
+                .into_mirrored(),
+            mode,
+        )
+        .expect("mirrored position is legal")
+    }
+
+    /// Checks if the position is supported in principle: it is decided
+    /// without tables (checkmate or insufficient material), or it has at
+    /// most 9 pieces (the most that mbeval can index) and no castling rights
+    /// (unless ignored, see [`Tablebase::set_ignore_castling_rights()`]).
+    /// Installed tables are not considered, see
+    /// [`Tablebase::has_tables_for()`].
+    pub fn is_in_scope(&self, pos: &Chess) -> bool {
+        pos.is_checkmate() || pos.is_insufficient_material() || self.in_scope(pos)
+    }
+
+    /// Checks if the `.mb` tables for both sides of `pos` have been added,
+    /// here or in a fallback, without opening them. Positions that are
+    /// decided without tables need none.
+    pub fn has_tables_for(&self, pos: &Chess) -> bool {
+        if pos.is_checkmate() || pos.is_insufficient_material() {
+            return true;
+        }
+        if !self.in_scope(pos) {
+            return false;
+        }
+
+        let setup = pos.clone().into_setup(self.ep_mode);
+        let material = pos.board().material();
+        let local = [
+            (setup.clone(), material),
+            (setup.into_mirrored(), flip_material(material)),
+        ]
+        .into_iter()
+        .all(|(setup, material)| {
+            // A lone white king never wins, so no table is needed.
+            if !setup.board.white().more_than_one() {
+                return true;
+            }
+            let Some(mb_info) = get_mb_info(&setup) else {
+                return false;
+            };
+            let key = table_key(setup.turn, material, &mb_info, TableType::Mb);
+            select(key, &mb_info, |key| {
+                Ok(self.tables.contains_key(key).then_some(()))
+            })
+            .is_ok_and(|found| found.is_some())
+        });
+
+        local
+            || self
+                .fallback
+                .as_ref()
+                .is_some_and(|fallback| fallback.has_tables_for(pos))
+    }
+
+    /// Checks if the position could be answered by some table, if installed.
+    fn in_scope(&self, pos: &Chess) -> bool {
+        pos.board().occupied().count() <= MAX_PIECES_MB as usize
+            && (self.ignore_castling_rights || !pos.castles().any())
+    }
+
+    /// Opens the `.mb` and `.hi` tables that probing `pos` could touch,
+    /// without reading any values. Useful to move the cost of opening
+    /// tables out of time-critical probes.
+    pub fn prewarm_position(&self, pos: &Chess) -> Result<(), ProbeError> {
+        if !self.in_scope(pos) {
+            return Ok(());
+        }
+
+        let setup = pos.clone().into_setup(self.ep_mode);
+        let material = pos.board().material();
+        for (setup, material) in [
+            (setup.clone(), material),
+            (setup.into_mirrored(), flip_material(material)),
+        ] {
+            if !setup.board.white().more_than_one() {
+                continue;
+            }
+            if let Some(mb_info) = get_mb_info(&setup) {
+                for table_type in [TableType::Mb, TableType::HighDtc] {
+                    self.select_table(setup.turn, material, &mb_info, table_type, None)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens all added tables on `threads` threads, calling `progress`
+    /// with the number of tables handled so far and the total after each
+    /// one. Failures do not stop preloading. Returns the tables that could
+    /// not be opened.
+    pub fn preload_all_with_progress(
+        &self,
+        threads: usize,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Vec<(TableKey, io::Error)> {
+        let keys: Vec<TableKey> = self.tables.keys().copied().collect();
+        let next = AtomicUsize::new(0);
+        let (tx, rx) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                let (keys, next, tx) = (&keys, &next, tx.clone());
+                scope.spawn(move || {
+                    while let Some(key) = keys.get(next.fetch_add(1, atomic::Ordering::Relaxed)) {
+                        let result = self.open_table(key, None).err().map(|err| (*key, err));
+                        if tx.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+
+            let mut errors = Vec::new();
+            for (done, result) in rx.into_iter().enumerate() {
+                errors.extend(result);
+                progress(done + 1, keys.len());
+            }
+            errors
+        })
+    }
+
+    /// Opens all added tables, to find missing or corrupt files up front
+    /// rather than at the first probe that touches them. Opened tables stay
+    /// open.
+    pub fn verify(&self) -> VerifyReport {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let mut failed: Vec<(PathBuf, TablebaseError)> = self
+            .preload_all_with_progress(threads, |_, _| ())
+            .into_iter()
+            .map(|(key, err)| (self.tables[&key].path.clone(), TablebaseError::from(err)))
+            .collect();
+        failed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        VerifyReport {
+            opened: self.tables.len() - failed.len(),
+            failed,
         }
-        let mb_info = unsafe { mb_info.assume_init() };
+    }
 
-        let Some((table, index)) = self.select_table(pos, &mb_info, TableType::Mb)? else {
+    /// Returns the indices mbeval computes for `pos`, as used to look up
+    /// the position in its tables, or `None` if mbeval does not handle the
+    /// position. Intended for debugging.
+    pub fn mb_info(&self, pos: &Chess) -> Result<Option<MbInfoSnapshot>, ProbeError> {
+        if !self.in_scope(pos) {
             return Ok(None);
-        };
+        }
+        let setup = pos.clone().into_setup(self.ep_mode);
+        Ok(get_mb_info(&setup).as_ref().map(MbInfoSnapshot::from))
+    }
+
+    /// Probes the DTC of a position. Corrupt tables are reported as
+    /// [`ProbeError::Tablebase`], unless configured otherwise with
+    /// [`Tablebase::set_corrupt_policy()`].
+    pub fn probe(&self, pos: &Chess) -> Result<Option<Value>, ProbeError> {
+        Ok(self.probe_value(pos, &mut ProbeContext::new()?)?)
+    }
+
+    /// Probes only win, draw, or loss. Unlike [`Tablebase::probe()`], this
+    /// can answer positions with high DTC without the `.hi` table, if
+    /// enabled with [`Tablebase::set_wdl_without_high_dtc()`].
+    pub fn probe_wdl(&self, pos: &Chess) -> Result<Option<Wdl>, ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+        if self.wdl_without_high_dtc {
+            ctx.set_wdl_only();
+        }
+        Ok(self.probe_value(pos, &mut ctx)?.map(Value::wdl))
+    }
+
+    /// Like [`Tablebase::probe()`], but spells out the DTC from both points
+    /// of view, to avoid confusion about the sign convention.
+    pub fn probe_signed(&self, pos: &Chess) -> Result<Option<SignedValue>, ProbeError> {
+        Ok(self.probe(pos)?.map(|value| {
+            let dtc = value.as_dtc().map_or(0, |dtc| dtc.0);
+            SignedValue {
+                turn: pos.turn(),
+                side_to_move_relative: dtc,
+                white_relative: relative_dtc(pos.turn(), dtc),
+                wdl: value.wdl(),
+            }
+        }))
+    }
+
+    /// Probes `pos` as if the side to move had passed (a null move), which
+    /// also discards en passant rights. Compare with [`Tablebase::probe()`]
+    /// to detect zugzwang. Returns `None` if the side to move is in check.
+    pub fn probe_null_move(&self, pos: &Chess) -> Result<Option<Value>, ProbeError> {
+        match pos.clone().swap_turn() {
+            Ok(pos) => Ok(self.probe(&pos)?),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Probes the result of a position from the point of view of `pov`,
+    /// regardless of the side to move. See [`Value::sign()`].
+    pub fn probe_pov(&self, pos: &Chess, pov: Color) -> Result<Option<Ordering>, ProbeError> {
+        Ok(self.probe(pos)?.map(|value| {
+            if pos.turn() == pov {
+                value.sign()
+            } else {
+                value.sign().reverse()
+            }
+        }))
+    }
+
+    /// Like [`Tablebase::probe()`], but gives up with
+    /// [`ProbeError::Timeout`] if opening tables (the slow part on cold
+    /// network storage) takes longer than `timeout` in total.
+    pub fn probe_timeout(
+        &self,
+        pos: &Chess,
+        timeout: Duration,
+    ) -> Result<Option<Value>, ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+        ctx.set_deadline(Instant::now() + timeout);
+        self.probe_value(pos, &mut ctx).map_err(|err| {
+            if err.kind() == io::ErrorKind::TimedOut {
+                ProbeError::Timeout
+            } else {
+                err.into()
+            }
+        })
+    }
+
+    /// Probes a position and all its children, in legal move order.
+    ///
+    /// Child values are from the point of view of the side to move after
+    /// the move. Buffers are shared across all probes, and children are
+    /// probed grouped by material, so that consecutive probes touch the same
+    /// tables.
+    #[allow(clippy::type_complexity)]
+    pub fn probe_position_and_children_in_one_pass(
+        &self,
+        pos: &Chess,
+    ) -> Result<(Option<Value>, Vec<(Move, Option<Value>)>), ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+
+        let parent = self.probe_value(pos, &mut ctx)?;
+
+        let children: Vec<(Move, Chess)> = pos
+            .legal_moves()
+            .into_iter()
+            .map(|m| {
+                let mut after = pos.clone();
+                after.play_unchecked(&m);
+                (m, after)
+            })
+            .collect();
+
+        // Quiet moves preserve material, so group by capture and promotion.
+        let mut order: Vec<usize> = (0..children.len()).collect();
+        order.sort_by_key(|&i| (children[i].0.capture(), children[i].0.promotion()));
+
+        let mut values = vec![None; children.len()];
+        for i in order {
+            values[i] = self.probe_value(&children[i].1, &mut ctx)?;
+        }
+
+        Ok((
+            parent,
+            children.into_iter().map(|(m, _)| m).zip(values).collect(),
+        ))
+    }
+
+    /// Probes every legal move, in legal move order. Unlike
+    /// [`Tablebase::probe_position_and_children_in_one_pass()`], values are
+    /// from the point of view of the side to move in `pos`, i.e. what the
+    /// move achieves. `None` if the position after the move cannot be
+    /// probed.
+    pub fn probe_moves(&self, pos: &Chess) -> Result<Vec<(Move, Option<Value>)>, ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+        pos.legal_moves()
+            .into_iter()
+            .map(|m| {
+                let mut after = pos.clone();
+                after.play_unchecked(&m);
+                let value = self.probe_value(&after, &mut ctx)?;
+                let value = value.map(|child| value_after_move(&m, child));
+                Ok((m, value))
+            })
+            .collect()
+    }
+
+    /// Counts the legal moves by the value they achieve for the side to
+    /// move (see [`Tablebase::best_move()`]), ordered from best to worst
+    /// value. Moves that cannot be probed are not counted.
+    pub fn child_value_histogram(&self, pos: &Chess) -> Result<Vec<(Value, u32)>, ProbeError> {
+        let (_, children) = self.probe_position_and_children_in_one_pass(pos)?;
+        let mut values: Vec<Value> = children
+            .into_iter()
+            .filter_map(|(m, child)| child.map(|child| value_after_move(&m, child)))
+            .collect();
+        values.sort_by_key(|value| Reverse(value.to_score(i32::MAX)));
+
+        let mut histogram: Vec<(Value, u32)> = Vec::new();
+        for value in values {
+            match histogram.last_mut() {
+                Some((last, count)) if *last == value => *count += 1,
+                _ => histogram.push((value, 1)),
+            }
+        }
+        Ok(histogram)
+    }
+
+    /// Probes the positions after each capture, e.g. to bridge the gap to
+    /// positions with one piece more than any table. Values are for the
+    /// side making the capture, as in [`Tablebase::best_move()`]. Captures
+    /// that cannot be probed are omitted.
+    pub fn probe_after_captures(&self, pos: &Chess) -> Result<Vec<(Move, Value)>, ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+        let mut values = Vec::new();
+        for m in pos.capture_moves() {
+            let mut after = pos.clone();
+            after.play_unchecked(&m);
+            if let Some(child) = self.probe_value(&after, &mut ctx)? {
+                values.push((m.clone(), value_after_move(&m, child)));
+            }
+        }
+        Ok(values)
+    }
+
+    /// Probes positions lazily and in order, sharing buffers across all
+    /// probes. Each result is paired with its position.
+    pub fn probe_iter<'a, I>(
+        &'a self,
+        positions: I,
+    ) -> impl Iterator<Item = (Chess, Result<Option<Value>, ProbeError>)> + 'a
+    where
+        I: IntoIterator<Item = Chess>,
+        I::IntoIter: 'a,
+    {
+        let mut ctx = ProbeContext::new();
+        positions.into_iter().map(move |pos| {
+            let result = match &mut ctx {
+                Ok(ctx) => self.probe_with_context(&pos, ctx),
+                Err(err) => Err(io::Error::new(err.kind(), err.to_string()).into()),
+            };
+            (pos, result)
+        })
+    }
+
+    /// Like [`Tablebase::probe_iter()`], but for async pipelines: positions
+    /// received from `positions` are probed on a blocking thread, and
+    /// results are sent in order. Probing stops when `positions` is closed
+    /// and drained, or when the returned receiver is dropped.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn probe_stream(
+        self: Arc<Self>,
+        mut positions: tokio::sync::mpsc::Receiver<Chess>,
+    ) -> ProbeStream {
+        let (tx, rx) = tokio::sync::mpsc::channel(positions.max_capacity());
+        tokio::task::spawn_blocking(move || {
+            let positions = std::iter::from_fn(|| positions.blocking_recv());
+            for item in self.probe_iter(positions) {
+                if tx.blocking_send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    /// Parses a FEN, in the same castling mode as used internally, and
+    /// probes the position.
+    pub fn probe_fen(&self, fen: &str) -> Result<Option<Value>, ProbeError> {
+        self.probe(&parse_fen(fen)?)
+    }
+
+    /// Probes the position of an EPD record. Operations like `bm` or `id`
+    /// are ignored.
+    pub fn probe_epd(&self, epd: &str) -> Result<Option<Value>, ProbeError> {
+        let fen = epd
+            .split_ascii_whitespace()
+            .take(4)
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.probe(&parse_fen(&fen)?)
+    }
+
+    /// Plays a line of SAN moves from `start` and probes the final position.
+    /// Fails with [`ProbeError::San`] at the first move that cannot be
+    /// played.
+    pub fn probe_san_line(
+        &self,
+        start: &Chess,
+        sans: &[&str],
+    ) -> Result<Option<Value>, ProbeError> {
+        let mut pos = start.clone();
+        for (ply, san) in sans.iter().enumerate() {
+            let m = san
+                .parse::<San>()
+                .ok()
+                .and_then(|parsed| parsed.to_move(&pos).ok())
+                .ok_or_else(|| ProbeError::San {
+                    ply,
+                    san: san.to_string(),
+                })?;
+            pos.play_unchecked(&m);
+        }
+        self.probe(&pos)
+    }
+
+    /// Probes positions given as FENs, with one result per FEN in the same
+    /// order. Invalid FENs only fail their own result. Positions are probed
+    /// grouped by endgame, sharing buffers.
+    pub fn probe_many_fens(&self, fens: &[&str]) -> Vec<Result<Option<Value>, ProbeError>> {
+        let mut results: Vec<Result<Option<Value>, ProbeError>> = Vec::with_capacity(fens.len());
+        let mut positions = Vec::new();
+        for (i, fen) in fens.iter().enumerate() {
+            match parse_fen(fen) {
+                Ok(pos) => {
+                    positions.push((i, pos));
+                    results.push(Ok(None));
+                }
+                Err(err) => results.push(Err(err)),
+            }
+        }
+        positions.sort_by_key(|(_, pos)| endgame_key(&pos.board().material()));
+
+        let mut ctx = ProbeContext::new();
+        for (i, pos) in positions {
+            results[i] = match &mut ctx {
+                Ok(ctx) => self.probe_with_context(&pos, ctx),
+                Err(err) => Err(io::Error::new(err.kind(), err.to_string()).into()),
+            };
+        }
+        results
+    }
+
+    /// Finds the best move for the side to move, together with the value
+    /// it achieves: the fastest conversion of a win, a draw, or the slowest
+    /// loss. Ties are broken as configured with
+    /// [`Tablebase::set_tie_break()`].
+    ///
+    /// Returns `None` if the position itself cannot be probed or there are
+    /// no legal moves.
+    pub fn best_move(&self, pos: &Chess) -> Result<Option<(Move, Value)>, ProbeError> {
+        Ok(self.best_move_with_context(pos, &mut ProbeContext::new()?)?)
+    }
+
+    fn best_move_with_context(
+        &self,
+        pos: &Chess,
+        ctx: &mut ProbeContext,
+    ) -> Result<Option<(Move, Value)>, io::Error> {
+        if self.probe_value(pos, ctx)?.is_none() {
+            return Ok(None);
+        }
+        self.best_child(pos, ctx)
+    }
+
+    /// Probes a position and finds its best move (see
+    /// [`Tablebase::best_move()`]) with shared buffers. The move is `None` if
+    /// the position cannot be probed or there are no legal moves.
+    pub fn probe_and_best_move(
+        &self,
+        pos: &Chess,
+    ) -> Result<(Option<Value>, Option<Move>), ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+        let Some(value) = self.probe_value(pos, &mut ctx)? else {
+            return Ok((None, None));
+        };
+        let best = self.best_child(pos, &mut ctx)?;
+        Ok((Some(value), best.map(|(m, _)| m)))
+    }
+
+    /// Finds the best move by probing all children, without probing the
+    /// position itself.
+    fn best_child(
+        &self,
+        pos: &Chess,
+        ctx: &mut ProbeContext,
+    ) -> Result<Option<(Move, Value)>, io::Error> {
+        let mut best: Option<(Move, Value)> = None;
+        for m in pos.legal_moves() {
+            let mut after = pos.clone();
+            after.play_unchecked(&m);
+            let Some(child) = self.probe_value(&after, ctx)? else {
+                continue;
+            };
+            let value = value_after_move(&m, child);
+            if best.as_ref().is_none_or(|(best_move, best_value)| {
+                value
+                    .to_score(i32::MAX)
+                    .cmp(&best_value.to_score(i32::MAX))
+                    .then_with(|| self.tie_break.compare(&m, best_move))
+                    .is_gt()
+            }) {
+                best = Some((m, value));
+            }
+        }
+        Ok(best)
+    }
+
+    /// Judges a legal move by comparing the value of the position before
+    /// and after it. Returns `None` if either position cannot be probed.
+    pub fn move_preserves(&self, pos: &Chess, m: &Move) -> Result<Option<MoveVerdict>, ProbeError> {
+        if !pos.is_legal(m) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "illegal move").into());
+        }
+
+        let mut ctx = ProbeContext::new()?;
+
+        let Some(before) = self.probe_value(pos, &mut ctx)? else {
+            return Ok(None);
+        };
+
+        let mut after = pos.clone();
+        after.play_unchecked(m);
+        let Some(child) = self.probe_value(&after, &mut ctx)? else {
+            return Ok(None);
+        };
+
+        let value = value_after_move(m, child);
+        Ok(Some(if value == before {
+            MoveVerdict::Optimal
+        } else if value.to_score(i32::MAX).signum() < before.to_score(i32::MAX).signum() {
+            MoveVerdict::Blunder
+        } else {
+            MoveVerdict::Preserving
+        }))
+    }
+
+    /// Classifies the position by its best move (see
+    /// [`Tablebase::best_move()`]): whether it is a capture or promotion,
+    /// or a quiet move. Returns `None` if there is no best move.
+    pub fn phase(&self, pos: &Chess) -> Result<Option<Phase>, ProbeError> {
+        Ok(self.best_move(pos)?.map(|(m, _)| {
+            if m.is_capture() || m.is_promotion() {
+                Phase::ConversionImminent
+            } else {
+                Phase::Maneuvering
+            }
+        }))
+    }
+
+    /// Follows best moves from a decisive position until the next
+    /// conversion (capture or promotion) or the end of the game.
+    ///
+    /// Returns an empty line for draws and positions that cannot be
+    /// probed. Fails with [`ProbeError::NonTerminatingLine`] if the line
+    /// gets longer than the DTC of the position allows, which indicates
+    /// inconsistent tables.
+    pub fn principal_variation(&self, pos: &Chess) -> Result<Vec<Move>, ProbeError> {
+        let mut ctx = ProbeContext::new()?;
+
+        let max_plies = match self.probe_value(pos, &mut ctx)? {
+            Some(Value::Dtc(dtc)) => dtc.plies().saturating_add(PV_SLACK_PLIES),
+            Some(Value::Draw | Value::Checkmated) | None => return Ok(Vec::new()),
+        };
+
+        let mut pos = pos.clone();
+        let mut line = Vec::new();
+        while let Some((m, _)) = self.best_move_with_context(&pos, &mut ctx)? {
+            if line.len() >= max_plies as usize {
+                return Err(ProbeError::NonTerminatingLine { line });
+            }
+            pos.play_unchecked(&m);
+            let conversion = m.is_capture() || m.is_promotion();
+            line.push(m);
+            if conversion {
+                break;
+            }
+        }
+        Ok(line)
+    }
+
+    /// Like [`Tablebase::probe()`], but reuses the buffers in `ctx`, saving
+    /// allocations when probing many positions in a loop.
+    pub fn probe_with_context(
+        &self,
+        pos: &Chess,
+        ctx: &mut ProbeContext,
+    ) -> Result<Option<Value>, ProbeError> {
+        Ok(self.probe_value(pos, ctx)?)
+    }
+
+    fn probe_value(&self, pos: &Chess, ctx: &mut ProbeContext) -> io::Result<Option<Value>> {
+        Ok(self
+            .probe_detailed_with_context(pos, pos.board().material(), ctx)?
+            .value())
+    }
+
+    /// Like [`Tablebase::probe()`], but distinguishes different kinds of
+    /// draws and unavailable results.
+    pub fn probe_detailed(&self, pos: &Chess) -> Result<ProbeOutcome, ProbeError> {
+        let material = pos.board().material();
+        Ok(self.probe_detailed_with_context(pos, material, &mut ProbeContext::new()?)?)
+    }
+
+    /// Like [`Tablebase::probe()`], but with the material of the position
+    /// already known, e.g. from incremental updates in a search.
+    ///
+    /// `material` must be `pos.board().material()`. Otherwise the result is
+    /// meaningless.
+    pub fn probe_with_material(
+        &self,
+        pos: &Chess,
+        material: &Material,
+    ) -> Result<Option<Value>, ProbeError> {
+        debug_assert_eq!(*material, pos.board().material());
+        Ok(self
+            .probe_detailed_with_context(pos, *material, &mut ProbeContext::new()?)?
+            .value())
+    }
+
+    /// Like [`Tablebase::probe_detailed()`], but wins and losses that cannot
+    /// be converted before the fifty-move rule applies, given the current
+    /// `halfmoves` clock, are reported as
+    /// [`DrawKind::FiftyMoveAdjusted`].
+    ///
+    /// This is an approximation: DTC counts moves to the next capture or
+    /// promotion, but intermediate pawn moves would also reset the clock.
+    pub fn probe_with_halfmove(
+        &self,
+        pos: &Chess,
+        halfmoves: u32,
+    ) -> Result<ProbeOutcome, ProbeError> {
+        self.probe_with_fifty_move_rule(pos, FiftyMoveRule::FromClock(halfmoves))
+    }
+
+    /// Like [`Tablebase::probe_detailed()`], applying the fifty-move rule as
+    /// stated by `rule`. See [`Tablebase::probe_with_halfmove()`] for the
+    /// caveats.
+    pub fn probe_with_fifty_move_rule(
+        &self,
+        pos: &Chess,
+        rule: FiftyMoveRule,
+    ) -> Result<ProbeOutcome, ProbeError> {
+        let halfmoves = match rule {
+            FiftyMoveRule::Ignore => return self.probe_detailed(pos),
+            FiftyMoveRule::FromClock(halfmoves) => halfmoves,
+            FiftyMoveRule::Strict => pos.halfmoves(),
+        };
+        Ok(match self.probe_detailed(pos)? {
+            ProbeOutcome::Dtc(dtc) if halfmoves.saturating_add(dtc.plies()) > 100 => {
+                ProbeOutcome::Draw(DrawKind::FiftyMoveAdjusted)
+            }
+            outcome => outcome,
+        })
+    }
+
+    fn probe_detailed_with_context(
+        &self,
+        pos: &Chess,
+        material: Material,
+        ctx: &mut ProbeContext,
+    ) -> Result<ProbeOutcome, io::Error> {
+        // Tables report checkmate as DTC 0, which is hard to tell apart from
+        // a position with play left.
+        if pos.is_checkmate() {
+            return Ok(ProbeOutcome::Checkmated);
+        }
+
+        if pos.is_insufficient_material() {
+            return Ok(ProbeOutcome::Draw(DrawKind::InsufficientMaterial));
+        }
+
+        if !self.in_scope(pos) {
+            return Ok(ProbeOutcome::OutOfScope);
+        }
+
+        if let Some(value) = self.builtin_solver.then(|| builtin::probe(pos)).flatten() {
+            return Ok(match value.as_dtc() {
+                Some(dtc) => ProbeOutcome::Dtc(dtc),
+                None => ProbeOutcome::Draw(DrawKind::OneSidedTablebaseDraw),
+            });
+        }
+
+        self.probe_setup(pos.clone().into_setup(self.ep_mode), material, ctx)
+    }
+
+    /// Reads the entry at `index` of an added table, e.g. to audit tables
+    /// with indices from [`Tablebase::mb_info()`].
+    ///
+    /// Tables only store wins for white (of the material in the key). The
+    /// result is relative to the side to move of the table, so it is a loss
+    /// if that is black. Returns `None` if white does not win, or if the
+    /// position is not listed in a `.hi` table. Fails with
+    /// [`io::ErrorKind::NotFound`] if a required table was not added.
+    pub fn read_value(&self, key: &TableKey, index: ZIndex) -> Result<Option<Value>, ProbeError> {
+        let not_found = |key: &TableKey| {
+            io::Error::new(io::ErrorKind::NotFound, format!("table not added: {key:?}"))
+        };
+        let table = self.open_table(key, None)?.ok_or_else(|| not_found(key))?;
+        let mut ctx = ProbeContext::new()?;
+
+        let dtc = match key.table_type {
+            TableType::HighDtc => table.lookup_high_dtc(index, &mut ctx)?,
+            TableType::Mb => match table.read_mb(index, &mut ctx)? {
+                MbValue::Dtc(dtc) => Some(i32::from(dtc)),
+                MbValue::Unresolved => None,
+                MbValue::MaybeHighDtc => {
+                    let key = TableKey {
+                        table_type: TableType::HighDtc,
+                        ..*key
+                    };
+                    let table = self
+                        .open_table(&key, None)?
+                        .ok_or_else(|| not_found(&key))?;
+                    match table.read_high_dtc(index, &mut ctx)? {
+                        SideValue::Dtc(dtc) => Some(dtc),
+                        SideValue::Unresolved => None,
+                    }
+                }
+            },
+        };
+        Ok(dtc.map(|dtc| Value::Dtc(Dtc(relative_dtc(key.side, dtc)))))
+    }
+
+    /// Probes a position given as a bare board, side to move, and en passant
+    /// square, without full legality checks.
+    ///
+    /// Only the number of kings and pieces is validated. Meant for tools that
+    /// generate positions in bulk. Insufficient material is not detected,
+    /// and the en passant square is used as given.
+    pub fn probe_board(
+        &self,
+        board: &Board,
+        turn: Color,
+        ep_square: Option<Square>,
+    ) -> Result<Option<Value>, ProbeError> {
+        if (board.kings() & board.white()).count() != 1
+            || (board.kings() & board.black()).count() != 1
+            || board.occupied().count() > MAX_PIECES_MB as usize
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "expected one king per side and at most 9 pieces",
+            )
+            .into());
+        }
+
+        let setup = Setup {
+            board: board.clone(),
+            turn,
+            ep_square,
+            ..Setup::empty()
+        };
+        Ok(self
+            .probe_setup(setup, board.material(), &mut ProbeContext::new()?)?
+            .value())
+    }
+
+    fn probe_setup(
+        &self,
+        pos: Setup,
+        material: Material,
+        ctx: &mut ProbeContext,
+    ) -> Result<ProbeOutcome, io::Error> {
+        match self.fallback {
+            None => self.probe_local(pos, material, ctx),
+            Some(ref fallback) => match self.probe_local(pos.clone(), material, ctx)? {
+                ProbeOutcome::TableMissing { .. } => fallback.probe_setup(pos, material, ctx),
+                outcome => Ok(outcome),
+            },
+        }
+    }
+
+    fn probe_local(
+        &self,
+        pos: Setup,
+        material: Material,
+        ctx: &mut ProbeContext,
+    ) -> Result<ProbeOutcome, io::Error> {
+        // Cheap early out for the common case in a search.
+        if !self.contains_material(&material) && !self.contains_material(&flip_material(material)) {
+            tracing::trace!("material not loaded");
+            return Ok(ProbeOutcome::TableMissing {
+                material,
+                pawn_file_type: None,
+            });
+        }
+
+        // Make the stronger side white to reduce the chance of having to probe the
+        // flipped position.
+        let (pos, material) = if needs_flip(&pos.board, &self.role_values) {
+            (pos.into_mirrored(), flip_material(material))
+        } else {
+            (pos, material)
+        };
+
+        let value = match self.probe_side(&pos, material, ctx) {
+            Err(error) if is_corrupt(&error) => return self.on_corrupt(error),
+            result => result?,
+        };
+        match value {
+            None => {
+                tracing::warn!("no table for {}", Fen(pos.clone()));
+                return Ok(missing_or_rejected(&pos, material));
+            }
+            Some(SideValue::Dtc(n)) => {
+                self.stats
+                    .true_predictions
+                    .fetch_add(1, atomic::Ordering::Relaxed);
+                return Ok(ProbeOutcome::Dtc(Dtc(relative_dtc(pos.turn, n))));
+            }
+            Some(SideValue::Unresolved) => (),
+        }
+
+        self.stats
+            .flipped_probes
+            .fetch_add(1, atomic::Ordering::Relaxed);
+        let pos = pos.into_mirrored();
+
+        let value = match self.probe_side(&pos, flip_material(material), ctx) {
+            Err(error) if is_corrupt(&error) => return self.on_corrupt(error),
+            result => result?,
+        };
+        Ok(match value {
+            None => {
+                tracing::warn!("no table for {} (flipped)", Fen(pos.clone()));
+                missing_or_rejected(&pos, flip_material(material))
+            }
+            Some(SideValue::Dtc(n)) => {
+                self.stats
+                    .false_predictions
+                    .fetch_add(1, atomic::Ordering::Relaxed);
+                ProbeOutcome::Dtc(Dtc(relative_dtc(pos.turn, n)))
+            }
+            Some(SideValue::Unresolved) => {
+                self.stats.draws.fetch_add(1, atomic::Ordering::Relaxed);
+                ProbeOutcome::Draw(
+                    if pos.board.white().more_than_one() && pos.board.black().more_than_one() {
+                        DrawKind::TablebaseDraw
+                    } else {
+                        DrawKind::OneSidedTablebaseDraw
+                    },
+                )
+            }
+        })
+    }
+
+    fn on_corrupt(&self, error: io::Error) -> Result<ProbeOutcome, io::Error> {
+        match self.corrupt_policy {
+            CorruptPolicy::Error => Err(error),
+            CorruptPolicy::Skip => {
+                tracing::warn!(%error, "skipping corrupt table");
+                Ok(ProbeOutcome::OutOfScope)
+            }
+            CorruptPolicy::Panic => panic!("corrupt table: {error}"),
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+}
+
+/// Result of probing a position, from the point of view of the side to
+/// move.
+///
+/// More kinds of results may be added in the future. Prefer the accessors
+/// over matching on the variants.
+///
+/// With the `serde` feature, values are serialized with a tag, like
+/// `{"type":"dtc","dtc":23}` or `{"type":"draw"}`.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "SerializedValue", from = "SerializedValue")
+)]
+#[non_exhaustive]
+pub enum Value {
+    Draw,
+    Dtc(Dtc),
+    /// The side to move is checkmated.
+    Checkmated,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SerializedValue {
+    Draw,
+    Dtc { dtc: i32 },
+    Checkmated,
+}
+
+#[cfg(feature = "serde")]
+impl From<Value> for SerializedValue {
+    fn from(value: Value) -> SerializedValue {
+        match value {
+            Value::Draw => SerializedValue::Draw,
+            Value::Dtc(Dtc(dtc)) => SerializedValue::Dtc { dtc },
+            Value::Checkmated => SerializedValue::Checkmated,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedValue> for Value {
+    fn from(value: SerializedValue) -> Value {
+        match value {
+            SerializedValue::Draw => Value::Draw,
+            SerializedValue::Dtc { dtc } => Value::Dtc(Dtc(dtc)),
+            SerializedValue::Checkmated => Value::Checkmated,
+        }
+    }
+}
+
+impl Value {
+    pub fn zero_draw(self) -> Option<i32> {
+        match self {
+            Value::Draw => Some(0),
+            Value::Dtc(Dtc(0)) | Value::Checkmated => None,
+            Value::Dtc(Dtc(dtc)) => Some(dtc),
+        }
+    }
+
+    /// Maps the value onto an engine score scale, from the point of view of
+    /// the side to move: `mate_bound - n` for a win with DTC `n`,
+    /// `-(mate_bound - n)` for a loss, `-mate_bound` if checkmated, and `0`
+    /// for a draw.
+    ///
+    /// `mate_bound` should exceed any DTC. Note that DTC is the distance to
+    /// conversion, not mate, so scores of different endgames are not
+    /// comparable as mate distances.
+    pub fn to_score(self, mate_bound: i32) -> i32 {
+        match self {
+            Value::Draw => 0,
+            Value::Dtc(Dtc(dtc)) if dtc > 0 => mate_bound.saturating_sub(dtc),
+            Value::Dtc(Dtc(dtc)) => mate_bound
+                .saturating_sub(dtc.saturating_neg())
+                .saturating_neg(),
+            Value::Checkmated => mate_bound.saturating_neg(),
+        }
+    }
+
+    /// Compares the value to a draw, from the point of view of the side to
+    /// move: `Greater` for a win, `Equal` for a draw, and `Less` for a loss.
+    /// `Dtc(0)` is a loss.
+    pub fn sign(self) -> Ordering {
+        match self {
+            Value::Draw => Ordering::Equal,
+            Value::Dtc(dtc) if dtc.is_win() => Ordering::Greater,
+            Value::Dtc(_) | Value::Checkmated => Ordering::Less,
+        }
+    }
+
+    /// The DTC, if the value is given as a DTC.
+    pub fn as_dtc(self) -> Option<Dtc> {
+        match self {
+            Value::Dtc(dtc) => Some(dtc),
+            Value::Draw | Value::Checkmated => None,
+        }
+    }
+
+    pub fn is_draw(self) -> bool {
+        self == Value::Draw
+    }
+
+    pub fn wdl(self) -> Wdl {
+        match self.sign() {
+            Ordering::Greater => Wdl::Win,
+            Ordering::Equal => Wdl::Draw,
+            Ordering::Less => Wdl::Loss,
+        }
+    }
+
+    /// Classifies the value under the fifty-move rule, assuming the
+    /// halfmove clock is reset at the current position: wins and losses
+    /// that take more than 100 plies to convert are cursed wins and blessed
+    /// losses.
+    ///
+    /// This is an approximation. DTC counts moves to the next capture or
+    /// promotion, but pawn moves on the way would also reset the clock, and
+    /// after the conversion the clock starts over. Exact adjudication could
+    /// differ.
+    pub fn game_result(self) -> GameResult {
+        match self {
+            Value::Draw => GameResult::Draw,
+            Value::Checkmated => GameResult::Loss,
+            Value::Dtc(dtc) => match (dtc.is_win(), dtc.plies() > 100) {
+                (true, false) => GameResult::Win,
+                (true, true) => GameResult::CursedWin,
+                (false, false) => GameResult::Loss,
+                (false, true) => GameResult::BlessedLoss,
+            },
+        }
+    }
+
+    /// Quantizes the value for compact storage. Wins and losses with a DTC
+    /// of at most `threshold` moves are short, all others are long.
+    /// Checkmate and `Dtc(0)` are short losses.
+    pub fn bucketed(self, threshold: u8) -> BucketedValue {
+        match self {
+            Value::Draw => BucketedValue::Draw,
+            Value::Checkmated => BucketedValue::LossShort,
+            Value::Dtc(dtc) => match (dtc.is_win(), dtc.abs() <= u32::from(threshold)) {
+                (true, true) => BucketedValue::WinShort,
+                (true, false) => BucketedValue::WinLong,
+                (false, true) => BucketedValue::LossShort,
+                (false, false) => BucketedValue::LossLong,
+            },
+        }
+    }
+
+    /// Encodes the value in 2 bytes, as a little-endian `i16`: the DTC
+    /// itself for `Dtc(n)`, `i16::MIN` for draws, and `i16::MIN + 1` for
+    /// checkmate. Returns `None` if the DTC is out of the range
+    /// `-32766..=32767`, which no table reaches.
+    pub fn to_bytes(self) -> Option<[u8; 2]> {
+        let encoded = match self {
+            Value::Draw => i16::MIN,
+            Value::Checkmated => i16::MIN + 1,
+            Value::Dtc(Dtc(dtc)) => i16::try_from(dtc).ok().filter(|&dtc| dtc > i16::MIN + 1)?,
+        };
+        Some(encoded.to_le_bytes())
+    }
+
+    /// Decodes a value encoded with [`Value::to_bytes()`]. Every input
+    /// decodes to some value.
+    pub fn from_bytes(bytes: [u8; 2]) -> Value {
+        match i16::from_le_bytes(bytes) {
+            i16::MIN => Value::Draw,
+            n if n == i16::MIN + 1 => Value::Checkmated,
+            dtc => Value::Dtc(Dtc(i32::from(dtc))),
+        }
+    }
+}
+
+/// Formats the value like `+23` for a win with DTC 23, `-17` for a loss with
+/// DTC -17, `draw`, or `checkmated`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Draw => f.write_str("draw"),
+            Value::Dtc(dtc) => fmt::Display::fmt(dtc, f),
+            Value::Checkmated => f.write_str("checkmated"),
+        }
+    }
+}
+
+/// Distance to conversion (capture or promotion, not mate) in moves, from
+/// the point of view of the side to move. Positive for wins, and zero or
+/// negative for losses.
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct Dtc(i32);
+
+impl Dtc {
+    pub const fn new(dtc: i32) -> Dtc {
+        Dtc(dtc)
+    }
+
+    pub const fn get(self) -> i32 {
+        self.0
+    }
+
+    pub fn is_win(self) -> bool {
+        self.0 > 0
+    }
+
+    /// `Dtc(0)` is a loss, e.g. when checkmated.
+    pub fn is_loss(self) -> bool {
+        !self.is_win()
+    }
+
+    /// Number of moves until the conversion.
+    pub fn abs(self) -> u32 {
+        self.0.unsigned_abs()
+    }
+
+    /// Number of plies until the conversion.
+    pub fn plies(self) -> u32 {
+        if self.is_win() {
+            self.abs() * 2 - 1
+        } else {
+            self.abs().saturating_mul(2)
+        }
+    }
+
+    /// The DTC before a move that is not a conversion, from the point of
+    /// view of the side making it, given the DTC after it. Saturates on
+    /// overflow.
+    pub fn add_ply(self) -> Dtc {
+        if self.is_win() {
+            Dtc(-self.0)
+        } else {
+            Dtc(self.0.saturating_neg().saturating_add(1))
+        }
+    }
+}
+
+/// Formats the DTC with an explicit sign, like `+23` or `-17`. `Dtc(0)` is
+/// formatted as `-0`, because it is a loss.
+impl fmt::Display for Dtc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_win() { '+' } else { '-' };
+        write!(f, "{sign}{}", self.abs())
+    }
+}
+
+/// Result of [`Tablebase::probe_signed()`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct SignedValue {
+    pub turn: Color,
+    /// DTC, positive if the side to move wins. `0` for draws and if the
+    /// side to move is checkmated.
+    pub side_to_move_relative: i32,
+    /// DTC, positive if white wins. `0` for draws and checkmate.
+    pub white_relative: i32,
+    /// Result for the side to move, telling apart the cases with DTC `0`.
+    pub wdl: Wdl,
+}
+
+/// Win, draw, or loss, from the point of view of the side to move. With the
+/// `serde` feature, serialized as `"win"`, `"draw"`, or `"loss"`.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+/// Result of [`Value::game_result()`], from the point of view of the side
+/// to move. Ordered from worst to best.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub enum GameResult {
+    Loss,
+    /// Lost, but the opponent cannot convert before the fifty-move rule.
+    BlessedLoss,
+    Draw,
+    /// Won, but not convertible before the fifty-move rule.
+    CursedWin,
+    Win,
+}
+
+/// Result of [`Value::bucketed()`], from the point of view of the side to
+/// move. Ordered from worst to best.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+pub enum BucketedValue {
+    LossShort,
+    LossLong,
+    Draw,
+    WinLong,
+    WinShort,
+}
+
+/// Verdict of [`Tablebase::move_preserves()`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MoveVerdict {
+    /// The move achieves the value of the position, e.g. it is among the
+    /// fastest conversions of a win.
+    Optimal,
+    /// The move keeps the result (win, draw, or loss), but not optimally.
+    Preserving,
+    /// The move worsens the result, e.g. it throws away a win.
+    Blunder,
+}
+
+/// What to do when a table turns out to be corrupt or truncated, see
+/// [`Tablebase::set_corrupt_policy()`].
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum CorruptPolicy {
+    /// Fail the probe with the error.
+    #[default]
+    Error,
+    /// Log a warning and treat the position as
+    /// [`ProbeOutcome::OutOfScope`], e.g. for long batch runs.
+    Skip,
+    /// Panic, e.g. for strict validation in tests.
+    Panic,
+}
+
+fn is_corrupt(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// How to choose among equally good moves, see
+/// [`Tablebase::set_tie_break()`].
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum TieBreak {
+    /// The move that comes first in legal move generation order. Fast, but
+    /// the order is an implementation detail of shakmaty.
+    #[default]
+    LegalMoveOrder,
+    /// The move with the lexicographically smallest UCI notation.
+    Uci,
+    /// Captures and promotions before quiet moves, then by UCI notation.
+    ConversionsFirst,
+}
+
+impl TieBreak {
+    /// Returns `Greater` if `a` is preferred over `b`.
+    fn compare(self, a: &Move, b: &Move) -> Ordering {
+        let uci = |m: &Move| m.to_uci(CastlingMode::Standard).to_string();
+        let conversion = |m: &Move| m.is_capture() || m.is_promotion();
+        match self {
+            TieBreak::LegalMoveOrder => Ordering::Less,
+            TieBreak::Uci => uci(b).cmp(&uci(a)),
+            TieBreak::ConversionsFirst => conversion(a)
+                .cmp(&conversion(b))
+                .then_with(|| uci(b).cmp(&uci(a))),
+        }
+    }
+}
+
+/// Result of [`Tablebase::phase()`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Phase {
+    /// The best move is a capture or promotion.
+    ConversionImminent,
+    /// The best move is quiet.
+    Maneuvering,
+}
+
+/// Detailed result of [`Tablebase::probe_detailed()`].
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ProbeOutcome {
+    /// Decisive, with DTC from the point of view of the side to move.
+    Dtc(Dtc),
+    /// The side to move is checkmated. No table is consulted.
+    Checkmated,
+    Draw(DrawKind),
+    /// Too many pieces or castling rights (unless ignored, see
+    /// [`Tablebase::set_ignore_castling_rights()`]). No table could answer
+    /// this.
+    OutOfScope,
+    /// The required table is not installed. Contains the material of the
+    /// missing table, from the point of view of the side that was probed,
+    /// and the pawn file type computed by mbeval, unless probing stopped
+    /// before indexing the position because neither side of the material
+    /// is installed.
+    TableMissing {
+        material: Material,
+        pawn_file_type: Option<PawnFileType>,
+    },
+    /// mbeval could not index the position, as passed to it (possibly
+    /// mirrored, so that the stronger side is white).
+    MbevalRejected {
+        turn: Color,
+        ep_square: Option<Square>,
+        material: Material,
+    },
+}
+
+impl ProbeOutcome {
+    /// Collapses the outcome to the result of [`Tablebase::probe()`].
+    pub fn value(self) -> Option<Value> {
+        match self {
+            ProbeOutcome::Dtc(dtc) => Some(Value::Dtc(dtc)),
+            ProbeOutcome::Checkmated => Some(Value::Checkmated),
+            ProbeOutcome::Draw(_) => Some(Value::Draw),
+            ProbeOutcome::OutOfScope
+            | ProbeOutcome::TableMissing { .. }
+            | ProbeOutcome::MbevalRejected { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DrawKind {
+    /// Neither side has mating material.
+    InsufficientMaterial,
+    /// Neither side can force a conversion, according to the tables.
+    TablebaseDraw,
+    /// The side with pieces cannot force a conversion, according to its
+    /// table. The side with a lone king cannot win, so no table is consulted
+    /// for it.
+    OneSidedTablebaseDraw,
+    /// Decisive, but the conversion would come too late to beat the
+    /// fifty-move rule.
+    FiftyMoveAdjusted,
+}
+
+/// How [`Tablebase::probe_with_fifty_move_rule()`] treats the fifty-move
+/// rule.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum FiftyMoveRule {
+    /// Report raw DTC values.
+    Ignore,
+    /// Count from the given halfmove clock. Conversions after more than 100
+    /// plies in total are draws.
+    FromClock(u32),
+    /// Count from the halfmove clock of the probed position, applying the
+    /// rule as in the game.
+    Strict,
+}
+
+/// An added table file, opened on demand.
+struct TableEntry {
+    path: PathBuf,
+    table: Mutex<Option<Arc<Table>>>,
+    /// Open that timed out, but is still running in the background.
+    pending: Mutex<Option<PendingOpen>>,
+    /// Value of the `clock` of the tablebase when last used.
+    last_used: AtomicU64,
+}
+
+type PendingOpen = mpsc::Receiver<io::Result<Table>>;
+
+impl TableEntry {
+    fn new(path: PathBuf) -> TableEntry {
+        TableEntry {
+            path,
+            table: Mutex::new(None),
+            pending: Mutex::new(None),
+            last_used: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Arc<Table>>> {
+        // The guarded state is consistent even if opening a table panicked.
+        self.table.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_open(&self) -> bool {
+        self.table.try_lock().is_ok_and(|table| table.is_some())
+    }
+}
+
+/// Device and inode of a file.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt as _;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Opens a table, giving up waiting at `deadline`.
+///
+/// With a deadline, the table is opened on a separate thread, which keeps
+/// running after a timeout. The thread is then parked in `pending`, so that
+/// the next attempt to open the same table picks up its result instead of
+/// starting another thread.
+fn open_with_deadline(
+    path: &Path,
+    table_type: TableType,
+    mmap: bool,
+    deadline: Option<Instant>,
+    pending: &Mutex<Option<PendingOpen>>,
+) -> io::Result<Table> {
+    let open = if mmap { Table::open_mmap } else { Table::open };
+    let rx = pending
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+    let Some(deadline) = deadline else {
+        // Without a deadline, wait for an open that is already running.
+        return match rx.map(|rx| rx.recv()) {
+            Some(Ok(result)) => result,
+            _ => open(path, table_type),
+        };
+    };
+
+    let rx = rx.unwrap_or_else(|| {
+        let (tx, rx) = mpsc::channel();
+        let thread_path = path.to_owned();
+        thread::spawn(move || {
+            // The receiver is gone if a racing open was parked instead.
+            let _ = tx.send(open(&thread_path, table_type));
+        });
+        rx
+    });
+    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            pending
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_or_insert(rx);
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out opening {}", path.display()),
+            ))
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(io::Error::other(format!(
+            "thread opening {} panicked",
+            path.display()
+        ))),
+    }
+}
+
+/// Identifies an added table file, see [`Tablebase::keys()`].
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub struct TableKey {
+    material: Material,
+    pawn_file_type: PawnFileType,
+    bishop_parity: ByColor<BishopParity>,
+    side: Color,
+    kk_index: KkIndex,
+    table_type: TableType,
+}
 
-        Ok(match table.read_mb(index, ctx)? {
-            MbValue::Dtc(dtc) => Some(SideValue::Dtc(i32::from(dtc))),
-            MbValue::Unresolved => Some(SideValue::Unresolved),
-            MbValue::MaybeHighDtc => self
-                .select_table(pos, &mb_info, TableType::HighDtc)?
-                .map(|(table, index)| table.read_high_dtc(index, ctx))
-                .transpose()?,
+impl TableKey {
+    /// Parses the key of a table file from its path, like
+    /// `kqk_out/kqk_w_0.mb`, as it would be added by
+    /// [`Tablebase::add_path()`].
+    pub fn from_path(path: impl AsRef<Path>) -> Option<TableKey> {
+        let path = path.as_ref();
+        let (material, pawn_file_type, bishop_parity) = parse_dirname(path.parent()?)?;
+        let (file_material, side, kk_index, table_type) = parse_filename(path)?;
+        (material == file_material).then_some(TableKey {
+            material,
+            pawn_file_type,
+            bishop_parity,
+            side,
+            kk_index,
+            table_type,
         })
     }
 
-    pub fn probe(&self, pos: &Chess) -> Result<Option<Value>, io::Error> {
-        if pos.is_insufficient_material() {
-            return Ok(Some(Value::Draw));
-        }
-
-        if pos.board().occupied().count() > 9 || pos.castles().any() {
-            return Ok(None);
-        }
+    pub fn material(&self) -> Material {
+        self.material
+    }
 
-        // Make the stronger side white to reduce the chance of having to probe the
-        // flipped position.
-        let pos = if strength(pos.board(), Color::White) < strength(pos.board(), Color::Black) {
-            flip_position(pos.clone())
-        } else {
-            pos.clone()
-        };
+    pub fn pawn_file_type(&self) -> PawnFileType {
+        self.pawn_file_type
+    }
 
-        let mut ctx = ProbeContext::new()?;
+    pub fn bishop_parity(&self) -> ByColor<BishopParity> {
+        self.bishop_parity
+    }
 
-        match self.probe_side(&pos, &mut ctx)? {
-            None => {
-                tracing::warn!(
-                    "no table for {}",
-                    Fen(pos.clone().into_setup(EnPassantMode::Legal))
-                );
-                return Ok(None);
-            }
-            Some(SideValue::Dtc(n)) => {
-                self.stats.true_predictions.fetch_add(1, Ordering::Relaxed);
-                return Ok(Some(Value::Dtc(pos.turn().fold_wb(n, n.saturating_neg()))));
-            }
-            Some(SideValue::Unresolved) => (),
-        }
+    /// Side to move in the positions covered by the table.
+    pub fn side(&self) -> Color {
+        self.side
+    }
 
-        let pos = flip_position(pos);
+    pub fn kk_index(&self) -> u32 {
+        self.kk_index.0
+    }
 
-        Ok(match self.probe_side(&pos, &mut ctx)? {
-            None => {
-                tracing::warn!(
-                    "no table for {} (flipped)",
-                    Fen(pos.clone().into_setup(EnPassantMode::Legal))
-                );
-                None
-            }
-            Some(SideValue::Dtc(n)) => {
-                self.stats.false_predictions.fetch_add(1, Ordering::Relaxed);
-                Some(Value::Dtc(pos.turn().fold_wb(n, n.saturating_neg())))
-            }
-            Some(SideValue::Unresolved) => {
-                self.stats.draws.fetch_add(1, Ordering::Relaxed);
-                Some(Value::Draw)
-            }
-        })
+    pub fn table_type(&self) -> TableType {
+        self.table_type
     }
+}
 
-    pub fn stats(&self) -> &Stats {
-        &self.stats
+/// Piece counts by color and role.
+pub type Material = ByColor<ByRole<u8>>;
+
+fn flip_material(material: Material) -> Material {
+    ByColor {
+        white: material.black,
+        black: material.white,
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-pub enum Value {
-    Draw,
-    Dtc(i32),
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct KkIndex(u32);
+
+/// Entry of [`Tablebase::index_to_json()`].
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct IndexEntry {
+    material: String,
+    pawn_file_type: String,
+    bishop_parity: [String; 2],
+    side: char,
+    kk_index: u32,
+    table_type: TableType,
+    path: String,
 }
 
-impl Value {
-    pub fn zero_draw(self) -> Option<i32> {
-        match self {
-            Value::Draw => Some(0),
-            Value::Dtc(0) => None,
-            Value::Dtc(dtc) => Some(dtc),
+/// Formats material like the table directories, e.g. `krbbpkqp`.
+fn material_name(material: &Material) -> String {
+    let mut name = String::new();
+    for side in [&material.white, &material.black] {
+        name.push('k');
+        for role in [
+            Role::Queen,
+            Role::Rook,
+            Role::Bishop,
+            Role::Knight,
+            Role::Pawn,
+        ] {
+            for _ in 0..side[role] {
+                name.push(role.char());
+            }
         }
     }
+    name
 }
 
-#[derive(Debug, Eq, Hash, PartialEq)]
-pub struct TableKey {
+/// Formats material in the conventional way, white pieces first, e.g.
+/// `KRBBPKQP`.
+pub fn material_string(material: &Material) -> String {
+    material_name(material).to_ascii_uppercase()
+}
+
+fn parse_fen(fen: &str) -> Result<Chess, ProbeError> {
+    Ok(fen.parse::<Fen>()?.into_position(CastlingMode::Chess960)?)
+}
+
+/// A table directory like `kqk_out`.
+struct TableDirectory {
+    path: PathBuf,
     material: Material,
     pawn_file_type: PawnFileType,
     bishop_parity: ByColor<BishopParity>,
-    side: Color,
-    kk_index: KkIndex,
-    table_type: TableType,
 }
 
-type Material = ByColor<ByRole<u8>>;
+impl TableDirectory {
+    /// Lists the files of the directory. Unreadable entries are `None`.
+    fn read_files(&self) -> io::Result<Vec<Option<PathBuf>>> {
+        Ok(self
+            .path
+            .read_dir()?
+            .map(|file| match file {
+                Ok(file) => Some(file.path()),
+                Err(error) => {
+                    tracing::warn!(%error, "skipping unreadable entry in {}", self.path.display());
+                    None
+                }
+            })
+            .collect())
+    }
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct KkIndex(u32);
+/// Finds the table directories in `path`, and, if `recursive`, at any depth
+/// below it. Fails only if `path` itself cannot be read.
+fn find_table_directories(path: &Path, recursive: bool) -> io::Result<Vec<TableDirectory>> {
+    let mut directories = Vec::new();
+    for entry in path.read_dir()? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                tracing::warn!(%error, "skipping unreadable entry in {}", path.display());
+                continue;
+            }
+        };
+        let directory = entry.path();
+        if let Some((material, pawn_file_type, bishop_parity)) = parse_dirname(&directory) {
+            directories.push(TableDirectory {
+                path: directory,
+                material,
+                pawn_file_type,
+                bishop_parity,
+            });
+        } else if recursive && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            match find_table_directories(&directory, recursive) {
+                Ok(nested) => directories.extend(nested),
+                Err(error) => tracing::warn!(%error, "skipping unreadable {}", directory.display()),
+            }
+        }
+    }
+    Ok(directories)
+}
+
+fn path_error(path: &Path, error: io::Error) -> TablebaseError {
+    match error.kind() {
+        io::ErrorKind::NotADirectory => TablebaseError::InvalidPath {
+            path: path.to_owned(),
+        },
+        _ => TablebaseError::Io(error),
+    }
+}
 
 fn parse_dirname(path: &Path) -> Option<(Material, PawnFileType, ByColor<BishopParity>)> {
     let name = path.file_name()?.to_str()?.strip_suffix("_out")?;
@@ -404,8 +2620,10 @@ fn parse_filename(path: &Path) -> Option<(Material, Color, KkIndex, TableType)>
     ))
 }
 
-fn parse_material(name: &str) -> Option<Material> {
-    if name.len() > 9 {
+pub(crate) fn parse_material(name: &str) -> Option<Material> {
+    // Byte length, but Role::from_char() rejects anything that is not ASCII
+    // anyway.
+    if name.len() > MAX_PIECES_MB as usize {
         return None;
     }
 
@@ -420,27 +2638,309 @@ fn parse_material(name: &str) -> Option<Material> {
                 Some(Color::Black) => return None,
             };
         };
-        material[color?][role] += 1;
+        let count = &mut material[color?][role];
+        *count = count.checked_add(1)?;
     }
 
     Some(material)
 }
 
-fn strength(board: &Board, color: Color) -> usize {
+/// Builds some legal position with the given material, with neither side
+/// in check. White is to move.
+fn sample_position(material: &Material) -> Option<Chess> {
+    if material.white.king != 1 || material.black.king != 1 {
+        return None;
+    }
+
+    let mut board = Board::empty();
+    board.set_piece_at(Square::A1, Role::King.of(Color::White));
+    board.set_piece_at(Square::H8, Role::King.of(Color::Black));
+    for color in Color::ALL {
+        for role in [
+            Role::Queen,
+            Role::Rook,
+            Role::Bishop,
+            Role::Knight,
+            Role::Pawn,
+        ] {
+            for _ in 0..material[color][role] {
+                let piece = role.of(color);
+                let sq = Square::ALL.into_iter().find(|&sq| {
+                    if board.piece_at(sq).is_some()
+                        || (role == Role::Pawn && Bitboard::BACKRANKS.contains(sq))
+                    {
+                        return false;
+                    }
+                    let mut board = board.clone();
+                    board.set_piece_at(sq, piece);
+                    Color::ALL.into_iter().all(|c| {
+                        board.king_of(c).is_some_and(|king| {
+                            board.attacks_to(king, !c, board.occupied()).is_empty()
+                        })
+                    })
+                })?;
+                board.set_piece_at(sq, piece);
+            }
+        }
+    }
+
+    Chess::from_setup(
+        Setup {
+            board,
+            ..Setup::empty()
+        },
+        CastlingMode::Standard,
+    )
+    .ok()
+}
+
+/// Tells apart positions that mbeval does not handle from missing tables,
+/// after [`Tablebase::probe_side()`] found no value.
+fn missing_or_rejected(pos: &Setup, material: Material) -> ProbeOutcome {
+    if let Some(mb_info) = get_mb_info(pos) {
+        ProbeOutcome::TableMissing {
+            material,
+            pawn_file_type: Some(mb_info.pawn_file_type),
+        }
+    } else {
+        ProbeOutcome::MbevalRejected {
+            turn: pos.turn,
+            ep_square: pos.ep_square,
+            material,
+        }
+    }
+}
+
+/// Retrieves the MB_INFO struct for a position.
+fn get_mb_info(pos: &Setup) -> Option<MbInfo> {
+    let mut squares = [mbeval_sys::Piece::NO_PIECE; 64];
+    for (sq, piece) in &pos.board {
+        let role = match piece.role {
+            Role::Pawn => mbeval_sys::Piece::PAWN,
+            Role::Knight => mbeval_sys::Piece::KNIGHT,
+            Role::Bishop => mbeval_sys::Piece::BISHOP,
+            Role::Rook => mbeval_sys::Piece::ROOK,
+            Role::Queen => mbeval_sys::Piece::QUEEN,
+            Role::King => mbeval_sys::Piece::KING,
+        };
+        squares[usize::from(sq)] = piece.color.fold_wb(role, -role);
+    }
+    mb_info_from_squares(&squares, pos.turn, pos.ep_square)
+}
+
+/// Retrieves the MB_INFO struct for a position in the square encoding of
+/// mbeval.
+fn mb_info_from_squares(
+    squares: &[mbeval_sys::Piece; 64],
+    turn: Color,
+    ep_square: Option<Square>,
+) -> Option<MbInfo> {
+    // mbeval_get_mb_info() clears the struct itself.
+    let mut mb_info: MaybeUninit<MbInfo> = MaybeUninit::uninit();
+    let result = unsafe {
+        mbeval_get_mb_info(
+            squares.as_ptr(),
+            turn.fold_wb(Side::White, Side::Black),
+            ep_square.map_or(0, c_int::from),
+            mb_info.as_mut_ptr(),
+        )
+    };
+    if result != 0 {
+        return None;
+    }
+    Some(unsafe { mb_info.assume_init() })
+}
+
+/// Computes the bishop parity of the given side, as used to select
+/// parity-constrained sub-tables: `Even` if all 2 or 3 bishops are on squares
+/// of the same color, `Odd` if 2 or 3 bishops are on both colors, and `None`
+/// otherwise. Always `None` if there are pawns on the board.
+pub fn bishop_parity(board: &Board, color: Color) -> BishopParity {
+    if board.pawns().any() {
+        return BishopParity::None;
+    }
+
+    let bishops = board.by_color(color) & board.bishops();
+    let light = (bishops & Bitboard::LIGHT_SQUARES).count();
+    let dark = (bishops & Bitboard::DARK_SQUARES).count();
+    match (light.max(dark), light.min(dark)) {
+        (2 | 3, 0) => BishopParity::Even,
+        (1 | 2, 1) => BishopParity::Odd,
+        _ => BishopParity::None,
+    }
+}
+
+/// A sub-table that could contain a position, and the index of the position
+/// in that sub-table.
+#[derive(Debug, PartialEq, Eq)]
+struct Candidate {
+    pawn_file_type: PawnFileType,
+    bishop_parity: ByColor<BishopParity>,
+    index: ZIndex,
+}
+
+/// Lists the sub-tables to try for the position described by `mb_info`, in
+/// order of preference. Does not touch the filesystem.
+fn candidates(mb_info: &MbInfo) -> impl Iterator<Item = Candidate> + '_ {
+    let parities = mb_info.parity_index[..num_parities(mb_info)]
+        .iter()
+        .map(|parity_index| Candidate {
+            pawn_file_type: PawnFileType::Free,
+            bishop_parity: ByColor {
+                white: parity_index.bishop_parity[Side::White as usize],
+                black: parity_index.bishop_parity[Side::Black as usize],
+            },
+            index: parity_index.index,
+        });
+
+    let pawn_files = match mb_info.pawn_file_type {
+        PawnFileType::Free => [None, None],
+        PawnFileType::Bp11 => [
+            Some((PawnFileType::Op11, mb_info.index_op_11)),
+            Some((PawnFileType::Bp11, mb_info.index_bp_11)),
+        ],
+        PawnFileType::Op11 => [Some((PawnFileType::Op11, mb_info.index_op_11)), None],
+        PawnFileType::Op21 => [Some((PawnFileType::Op21, mb_info.index_op_21)), None],
+        PawnFileType::Op12 => [Some((PawnFileType::Op12, mb_info.index_op_12)), None],
+        PawnFileType::Op22 => [Some((PawnFileType::Op22, mb_info.index_op_22)), None],
+        PawnFileType::Dp22 => [
+            Some((PawnFileType::Op22, mb_info.index_op_22)),
+            Some((PawnFileType::Dp22, mb_info.index_dp_22)),
+        ],
+        PawnFileType::Op31 => [Some((PawnFileType::Op31, mb_info.index_op_31)), None],
+        PawnFileType::Op13 => [Some((PawnFileType::Op13, mb_info.index_op_13)), None],
+        PawnFileType::Op41 => [Some((PawnFileType::Op41, mb_info.index_op_41)), None],
+        PawnFileType::Op14 => [Some((PawnFileType::Op14, mb_info.index_op_14)), None],
+        PawnFileType::Op32 => [Some((PawnFileType::Op32, mb_info.index_op_32)), None],
+        PawnFileType::Op23 => [Some((PawnFileType::Op23, mb_info.index_op_23)), None],
+        PawnFileType::Op33 => [Some((PawnFileType::Op33, mb_info.index_op_33)), None],
+        PawnFileType::Op42 => [Some((PawnFileType::Op42, mb_info.index_op_42)), None],
+        PawnFileType::Op24 => [Some((PawnFileType::Op24, mb_info.index_op_24)), None],
+    };
+
+    parities.chain(
+        pawn_files
+            .into_iter()
+            .flatten()
+            .filter(|&(_, index)| index != ALL_ONES)
+            .map(|(pawn_file_type, index)| Candidate {
+                pawn_file_type,
+                bishop_parity: ByColor::new_with(|_| BishopParity::None),
+                index,
+            }),
+    )
+}
+
+/// Key of the table for a position with `turn` to move, before choosing among
+/// pawn file types and bishop parities.
+fn table_key(turn: Color, material: Material, mb_info: &MbInfo, table_type: TableType) -> TableKey {
+    TableKey {
+        material,
+        pawn_file_type: PawnFileType::Free,
+        bishop_parity: ByColor::new_with(|_| BishopParity::None),
+        side: turn,
+        kk_index: KkIndex(mb_info.kk_index as u32),
+        table_type,
+    }
+}
+
+/// Returns the first candidate sub-table for `mb_info` that `open` provides,
+/// together with the index into it. `table_key` determines everything but
+/// pawn file type and bishop parity.
+fn select<T>(
+    table_key: TableKey,
+    mb_info: &MbInfo,
+    mut open: impl FnMut(&TableKey) -> io::Result<Option<T>>,
+) -> io::Result<Option<(T, ZIndex)>> {
+    for candidate in candidates(mb_info) {
+        if let Some(table) = open(&TableKey {
+            pawn_file_type: candidate.pawn_file_type,
+            bishop_parity: candidate.bishop_parity,
+            ..table_key
+        })? {
+            return Ok(Some((table, candidate.index)));
+        }
+    }
+    Ok(None)
+}
+
+/// Decides if a position should be mirrored before probing, so that the
+/// stronger side is white.
+fn needs_flip(board: &Board, role_values: &ByRole<usize>) -> bool {
+    strength(board, Color::White, role_values) < strength(board, Color::Black, role_values)
+}
+
+/// Extra plies allowed in a principal variation beyond what the DTC implies.
+const PV_SLACK_PLIES: u32 = 10;
+
+/// Computes the value of a move for the side making it, given the value of
+/// the resulting position.
+fn value_after_move(m: &Move, child: Value) -> Value {
+    match child {
+        Value::Draw => Value::Draw,
+        Value::Checkmated => Value::Dtc(Dtc(1)),
+        // Conversions reset the distance.
+        Value::Dtc(dtc) if m.is_capture() || m.is_promotion() => {
+            Value::Dtc(Dtc(if dtc.is_loss() { 1 } else { -1 }))
+        }
+        Value::Dtc(dtc) => Value::Dtc(dtc.add_ply()),
+    }
+}
+
+/// Converts a DTC for white, as stored in the tables, to the point of view of
+/// the side to move. Saturates rather than overflowing on high DTC values.
+fn relative_dtc(turn: Color, dtc: i32) -> i32 {
+    turn.fold_wb(dtc, dtc.saturating_neg())
+}
+
+const DEFAULT_ROLE_VALUES: ByRole<usize> = ByRole {
+    pawn: 1,
+    knight: 3,
+    bishop: 3,
+    rook: 5,
+    queen: 9,
+    king: 0,
+};
+
+fn strength(board: &Board, color: Color, role_values: &ByRole<usize>) -> usize {
     let side = board.by_color(color);
-    (side & board.pawns()).count()
-        + (side & board.knights()).count() * 3
-        + (side & board.bishops()).count() * 3
-        + (side & board.rooks()).count() * 5
-        + (side & board.queens()).count() * 9
+    Role::ALL
+        .into_iter()
+        .map(|role| (side & board.by_role(role)).count() * role_values[role])
+        .sum()
 }
 
-#[must_use]
-fn flip_position(pos: Chess) -> Chess {
-    pos.into_setup(EnPassantMode::Legal)
-        .into_mirrored()
-        .position(CastlingMode::Chess960)
-        .expect("equivalent position")
+/// Result of [`Tablebase::diff()`]. Keys are in arbitrary order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TablebaseDiff {
+    pub only_in_self: Vec<TableKey>,
+    pub only_in_other: Vec<TableKey>,
+}
+
+impl TablebaseDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty()
+    }
+}
+
+fn files_equal(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    let mut a = io::BufReader::new(fs::File::open(a)?);
+    let mut b = io::BufReader::new(fs::File::open(b)?);
+    loop {
+        let chunk = a.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(b.fill_buf()?.is_empty());
+        }
+        let n = chunk.len().min(b.fill_buf()?.len());
+        if n == 0 || a.buffer()[..n] != b.buffer()[..n] {
+            return Ok(false);
+        }
+        a.consume(n);
+        b.consume(n);
+    }
 }
 
 #[derive(Default)]
@@ -448,6 +2948,7 @@ pub struct Stats {
     draws: AtomicU64,
     true_predictions: AtomicU64,
     false_predictions: AtomicU64,
+    flipped_probes: AtomicU64,
 }
 
 impl Stats {
@@ -456,14 +2957,546 @@ impl Stats {
     }
 
     pub fn draws(&self) -> u64 {
-        self.draws.load(Ordering::Relaxed)
+        self.draws.load(atomic::Ordering::Relaxed)
     }
 
     pub fn true_predictions(&self) -> u64 {
-        self.true_predictions.load(Ordering::Relaxed)
+        self.true_predictions.load(atomic::Ordering::Relaxed)
     }
 
     pub fn false_predictions(&self) -> u64 {
-        self.false_predictions.load(Ordering::Relaxed)
+        self.false_predictions.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Number of times the first side probed did not win, so that the
+    /// mirrored position had to be probed as well.
+    pub fn flipped_probes(&self) -> u64 {
+        self.flipped_probes.load(atomic::Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::uci::UciMove;
+
+    use super::*;
+
+    fn zeroed_mb_info() -> MbInfo {
+        unsafe { MaybeUninit::zeroed().assume_init() }
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Tablebase>();
+    }
+
+    #[test]
+    fn test_mb_info() {
+        let tb = Tablebase::new();
+        let pos = parse_fen("8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1").unwrap();
+        let mb_info = tb.mb_info(&pos).unwrap().unwrap();
+        assert_eq!(mb_info.num_pieces, 8);
+        assert!(!mb_info.parity_indices.is_empty());
+
+        assert_eq!(tb.mb_info(&Chess::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_checkmate() {
+        let tb = Tablebase::new();
+        for fen in [
+            "k7/1Q6/1K6/8/8/8/8/8 b - - 0 1",
+            "R5k1/8/6K1/8/8/8/8/8 b - - 0 1",
+        ] {
+            let pos = parse_fen(fen).unwrap();
+            assert_eq!(tb.probe(&pos).unwrap(), Some(Value::Checkmated), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_tie_break() {
+        let pos = parse_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let m = |uci: &str| uci.parse::<UciMove>().unwrap().to_move(&pos).unwrap();
+        let (capture, push, king) = (m("e4d5"), m("e4e5"), m("e1d1"));
+
+        assert_eq!(
+            TieBreak::LegalMoveOrder.compare(&push, &king),
+            Ordering::Less
+        );
+        assert_eq!(TieBreak::Uci.compare(&king, &push), Ordering::Greater);
+        assert_eq!(TieBreak::Uci.compare(&capture, &push), Ordering::Greater);
+        assert_eq!(
+            TieBreak::ConversionsFirst.compare(&capture, &king),
+            Ordering::Greater
+        );
+        assert_eq!(
+            TieBreak::ConversionsFirst.compare(&king, &push),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_open_with_deadline_pending() {
+        let pending = Mutex::new(None);
+        let path = Path::new("missing.mb");
+        let timed = open_with_deadline(path, TableType::Mb, false, Some(Instant::now()), &pending);
+        assert!(timed.is_err());
+
+        // Picks up the result of the timed out open, if it was parked.
+        let err = open_with_deadline(path, TableType::Mb, false, None, &pending).err();
+        assert_eq!(err.map(|err| err.kind()), Some(io::ErrorKind::NotFound));
+        assert!(pending.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_candidates_parities_first() {
+        let mut mb_info = zeroed_mb_info();
+        mb_info.num_parities = 2;
+        mb_info.parity_index[0].index = 10;
+        mb_info.parity_index[1].index = 11;
+        mb_info.parity_index[1].bishop_parity = [BishopParity::Even, BishopParity::None];
+        mb_info.pawn_file_type = PawnFileType::Op11;
+        mb_info.index_op_11 = 12;
+
+        let candidates: Vec<_> = candidates(&mb_info).collect();
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0].index, 10);
+        assert_eq!(candidates[1].bishop_parity.white, BishopParity::Even);
+        assert_eq!(candidates[2].pawn_file_type, PawnFileType::Op11);
+        assert_eq!(candidates[2].index, 12);
+    }
+
+    #[test]
+    fn test_candidates_pawn_file_fallback() {
+        let mut mb_info = zeroed_mb_info();
+        mb_info.pawn_file_type = PawnFileType::Dp22;
+        mb_info.index_op_22 = ALL_ONES;
+        mb_info.index_dp_22 = 7;
+
+        let candidates: Vec<_> = candidates(&mb_info).collect();
+        assert_eq!(
+            candidates,
+            [Candidate {
+                pawn_file_type: PawnFileType::Dp22,
+                bishop_parity: ByColor::new_with(|_| BishopParity::None),
+                index: 7,
+            }]
+        );
+
+        mb_info.pawn_file_type = PawnFileType::Free;
+        assert_eq!(super::candidates(&mb_info).count(), 0);
+    }
+
+    #[test]
+    fn test_candidates_num_parities_out_of_bounds() {
+        let mut mb_info = zeroed_mb_info();
+        mb_info.num_parities = 1000;
+        assert_eq!(candidates(&mb_info).count(), mb_info.parity_index.len());
+
+        mb_info.num_parities = -1;
+        assert_eq!(candidates(&mb_info).count(), 0);
+    }
+
+    fn free_key() -> TableKey {
+        TableKey {
+            material: parse_material("kbbpkp").unwrap(),
+            pawn_file_type: PawnFileType::Free,
+            bishop_parity: ByColor::new_with(|_| BishopParity::None),
+            side: Color::White,
+            kk_index: KkIndex(0),
+            table_type: TableType::Mb,
+        }
+    }
+
+    /// Selects among the given installed tables, returning the chosen key.
+    fn select_installed(
+        mb_info: &MbInfo,
+        installed: &[TableKey],
+    ) -> Option<(PawnFileType, ByColor<BishopParity>, ZIndex)> {
+        select(free_key(), mb_info, |key| {
+            Ok(installed.contains(key).then_some(*key))
+        })
+        .unwrap()
+        .map(|(key, index)| (key.pawn_file_type, key.bishop_parity, index))
+    }
+
+    #[test]
+    fn test_select() {
+        let mut mb_info = zeroed_mb_info();
+        mb_info.num_parities = 2;
+        mb_info.parity_index[0].index = 10;
+        mb_info.parity_index[1].index = 11;
+        mb_info.parity_index[1].bishop_parity = [BishopParity::Even, BishopParity::None];
+        mb_info.pawn_file_type = PawnFileType::Op11;
+        mb_info.index_op_11 = 12;
+
+        let free = free_key();
+        let even = TableKey {
+            bishop_parity: ByColor {
+                white: BishopParity::Even,
+                black: BishopParity::None,
+            },
+            ..free
+        };
+        let op11 = TableKey {
+            pawn_file_type: PawnFileType::Op11,
+            ..free
+        };
+
+        // Unconstrained table preferred.
+        assert_eq!(
+            select_installed(&mb_info, &[op11, even, free]),
+            Some((PawnFileType::Free, free.bishop_parity, 10))
+        );
+        // Then bishop parity.
+        assert_eq!(
+            select_installed(&mb_info, &[op11, even]),
+            Some((PawnFileType::Free, even.bishop_parity, 11))
+        );
+        // Then pawn files.
+        assert_eq!(
+            select_installed(&mb_info, &[op11]),
+            Some((PawnFileType::Op11, free.bishop_parity, 12))
+        );
+        assert_eq!(select_installed(&mb_info, &[]), None);
+
+        // Not applicable pawn file sub-tables are never selected.
+        mb_info.index_op_11 = ALL_ONES;
+        assert_eq!(select_installed(&mb_info, &[op11]), None);
+    }
+
+    #[test]
+    fn test_select_error() {
+        let mut mb_info = zeroed_mb_info();
+        mb_info.num_parities = 1;
+        let result: io::Result<Option<((), ZIndex)>> = select(free_key(), &mb_info, |_| {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt"))
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bishop_parity() {
+        let board: Board = "4k3/8/8/8/8/8/8/2B1KB1n".parse().unwrap();
+        assert_eq!(bishop_parity(&board, Color::White), BishopParity::Odd);
+        assert_eq!(bishop_parity(&board, Color::Black), BishopParity::None);
+
+        let board: Board = "4k3/8/8/8/8/8/8/2B1K2n".parse().unwrap();
+        assert_eq!(bishop_parity(&board, Color::White), BishopParity::None);
+
+        let board: Board = "4k3/8/8/8/8/8/1B6/2B1K2n".parse().unwrap();
+        assert_eq!(bishop_parity(&board, Color::White), BishopParity::Even);
+
+        let board: Board = "4k3/7p/8/8/8/8/1B6/2B1K2n".parse().unwrap();
+        assert_eq!(bishop_parity(&board, Color::White), BishopParity::None);
+    }
+
+    #[test]
+    fn test_bishop_parity_matches_mbeval() {
+        Tablebase::new(); // Implies mbeval_init
+
+        for fen in [
+            "4k3/8/8/8/8/8/8/2B1KB1n w - - 0 1",
+            "4k3/8/8/8/8/8/1B6/2B1K2n w - - 0 1",
+            "4k3/8/8/8/8/8/1B6/2B1K1bn b - - 0 1",
+        ] {
+            let setup = fen.parse::<Fen>().unwrap().into_setup();
+            let mb_info = get_mb_info(&setup).expect("mb info");
+            for candidate in candidates(&mb_info) {
+                for color in [Color::White, Color::Black] {
+                    let parity = candidate.bishop_parity[color];
+                    assert!(
+                        parity == BishopParity::None
+                            || parity == bishop_parity(&setup.board, color),
+                        "{fen}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_needs_flip() {
+        let values = DEFAULT_ROLE_VALUES;
+        assert!(!needs_flip(&Board::new(), &values));
+        assert!(needs_flip(
+            &"4k3/8/8/8/8/8/8/2q1K3".parse().unwrap(),
+            &values
+        ));
+        assert!(!needs_flip(
+            &"4k3/8/8/8/8/8/8/2Q1K3".parse().unwrap(),
+            &values
+        ));
+
+        let board = "4k3/1b6/8/8/8/8/PP6/4K3".parse().unwrap();
+        assert!(needs_flip(&board, &values));
+        let values = ByRole { pawn: 2, ..values };
+        assert!(!needs_flip(&board, &values));
+    }
+
+    #[test]
+    fn test_parse_material() {
+        let material = parse_material("kqqqk").unwrap();
+        assert_eq!(material.white.queen, 3);
+        assert_eq!(material.black.king, 1);
+
+        assert!(parse_material("kpppppppk").is_some());
+        assert!(parse_material("kppppppppk").is_none());
+        assert!(parse_material("kqk\u{e9}").is_none());
+        assert!(parse_material("k\u{e9}\u{e9}\u{e9}").is_none());
+        assert!(parse_material("qk").is_none());
+        assert!(parse_material("kkk").is_none());
+    }
+
+    #[test]
+    fn test_material_name() {
+        for name in ["krbbpkqp", "kqk", "kbpkpppp", "kpppppppk"] {
+            assert_eq!(material_name(&parse_material(name).unwrap()), name);
+            assert_eq!(
+                material_string(&parse_material(name).unwrap()),
+                name.to_ascii_uppercase()
+            );
+        }
+    }
+
+    #[test]
+    fn test_duplicate_roles() {
+        Tablebase::new(); // Implies mbeval_init
+
+        for (fen, name) in [
+            ("4k3/8/8/8/8/8/8/2QQK3 w - - 0 1", "kqqk"),
+            ("4k3/8/8/8/8/8/8/R3K2R b - - 0 1", "krrk"),
+            ("4k3/8/8/8/8/8/8/2QQKQ2 w - - 0 1", "kqqqk"),
+        ] {
+            let setup = fen.parse::<Fen>().unwrap().into_setup();
+            let (material, _, _, _) =
+                parse_filename(Path::new(&format!("{name}_w_0.mb"))).expect("filename");
+            assert_eq!(setup.board.material(), material, "{fen}");
+
+            let mb_info = get_mb_info(&setup).expect("mb info");
+            assert_eq!(
+                mb_info.num_pieces,
+                setup.board.occupied().count() as i32,
+                "{fen}"
+            );
+            assert!(candidates(&mb_info).count() > 0, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_sample_position() {
+        for name in ["kk", "kqqk", "kpkp", "kpppppppk", "kbbnkrp"] {
+            let material = parse_material(name).unwrap();
+            let pos = sample_position(&material).expect(name);
+            assert_eq!(pos.board().material(), material, "{name}");
+            assert!(!pos.is_check(), "{name}");
+        }
+
+        assert!(sample_position(&parse_material("kqk").map(flip_material).unwrap()).is_some());
+        assert!(sample_position(&Material::default()).is_none());
+    }
+
+    #[test]
+    fn test_validate_material_loadable_without_tables() {
+        let tb = Tablebase::new();
+        let kbk = parse_material("kbk").unwrap();
+        assert_eq!(tb.validate_material_loadable(&kbk).unwrap(), Some(true));
+        let kqk = parse_material("kqk").unwrap();
+        assert_eq!(tb.validate_material_loadable(&kqk).unwrap(), Some(false));
+        assert_eq!(
+            tb.validate_material_loadable(&Material::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_value_after_move() {
+        let pos = Chess::default();
+        let quiet = pos.legal_moves()[0].clone();
+        assert_eq!(value_after_move(&quiet, Value::Draw), Value::Draw);
+        assert_eq!(
+            value_after_move(&quiet, Value::Dtc(Dtc(0))),
+            Value::Dtc(Dtc(1))
+        );
+        assert_eq!(
+            value_after_move(&quiet, Value::Checkmated),
+            Value::Dtc(Dtc(1))
+        );
+        assert_eq!(
+            value_after_move(&quiet, Value::Dtc(Dtc(-5))),
+            Value::Dtc(Dtc(6))
+        );
+        assert_eq!(
+            value_after_move(&quiet, Value::Dtc(Dtc(5))),
+            Value::Dtc(Dtc(-5))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_value() {
+        for value in [
+            Value::Draw,
+            Value::Checkmated,
+            Value::Dtc(Dtc(23)),
+            Value::Dtc(Dtc(0)),
+            Value::Dtc(Dtc(i32::MIN)),
+            Value::Dtc(Dtc(i32::MAX)),
+        ] {
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Value>(&json).unwrap(),
+                value,
+                "{json}"
+            );
+        }
+        assert_eq!(
+            serde_json::to_string(&Value::Dtc(Dtc(23))).unwrap(),
+            r#"{"type":"dtc","dtc":23}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Value::Draw).unwrap(),
+            r#"{"type":"draw"}"#
+        );
+        assert_eq!(serde_json::to_string(&Wdl::Win).unwrap(), r#""win""#);
+        assert_eq!(serde_json::from_str::<Wdl>(r#""loss""#).unwrap(), Wdl::Loss);
+    }
+
+    #[test]
+    fn test_display_value() {
+        assert_eq!(Value::Dtc(Dtc(23)).to_string(), "+23");
+        assert_eq!(Value::Dtc(Dtc(-17)).to_string(), "-17");
+        assert_eq!(Value::Dtc(Dtc(0)).to_string(), "-0");
+        assert_eq!(Value::Dtc(Dtc(i32::MIN)).to_string(), "-2147483648");
+        assert_eq!(Value::Draw.to_string(), "draw");
+        assert_eq!(Value::Checkmated.to_string(), "checkmated");
+    }
+
+    #[test]
+    fn test_dtc_plies() {
+        assert_eq!(Dtc(1).plies(), 1);
+        assert_eq!(Dtc(50).plies(), 99);
+        assert_eq!(Dtc(-50).plies(), 100);
+        assert_eq!(Dtc(0).plies(), 0);
+        assert_eq!(Dtc(i32::MIN).plies(), u32::MAX);
+        assert!(Dtc(0).is_loss());
+        assert_eq!(Dtc(i32::MIN).add_ply(), Dtc(i32::MAX));
+    }
+
+    #[test]
+    fn test_fifty_move_rule_strict() {
+        let mut tb = Tablebase::new();
+        tb.set_builtin_solver(true);
+        let fen = |halfmoves: u32| format!("8/8/8/4k3/8/8/8/KQ6 w - - {halfmoves} 80");
+        let ProbeOutcome::Dtc(dtc) = tb.probe_detailed(&parse_fen(&fen(0)).unwrap()).unwrap()
+        else {
+            panic!("expected decisive outcome");
+        };
+        let limit = 100 - dtc.plies();
+        for (halfmoves, expected) in [
+            (limit, ProbeOutcome::Dtc(dtc)),
+            (limit + 1, ProbeOutcome::Draw(DrawKind::FiftyMoveAdjusted)),
+        ] {
+            let pos = parse_fen(&fen(halfmoves)).unwrap();
+            assert_eq!(
+                tb.probe_with_fifty_move_rule(&pos, FiftyMoveRule::Strict)
+                    .unwrap(),
+                expected
+            );
+            assert_eq!(
+                tb.probe_with_fifty_move_rule(&pos, FiftyMoveRule::FromClock(0))
+                    .unwrap(),
+                ProbeOutcome::Dtc(dtc)
+            );
+        }
+    }
+
+    #[test]
+    fn test_dtc_display() {
+        assert_eq!(Dtc::new(-3).get(), -3);
+        assert_eq!(Dtc::new(3).to_string(), "+3");
+        assert_eq!(Dtc::new(0).to_string(), "-0");
+    }
+
+    #[test]
+    fn test_to_score() {
+        assert_eq!(Value::Draw.to_score(30_000), 0);
+        assert_eq!(Value::Dtc(Dtc(12)).to_score(30_000), 29_988);
+        assert_eq!(Value::Dtc(Dtc(-12)).to_score(30_000), -29_988);
+        assert_eq!(Value::Dtc(Dtc(0)).to_score(30_000), -30_000);
+        assert_eq!(Value::Checkmated.to_score(30_000), -30_000);
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(Value::Draw.sign(), Ordering::Equal);
+        assert_eq!(Value::Dtc(Dtc(1)).sign(), Ordering::Greater);
+        assert_eq!(Value::Dtc(Dtc(0)).sign(), Ordering::Less);
+        assert_eq!(Value::Dtc(Dtc(-1)).sign(), Ordering::Less);
+        assert_eq!(Value::Checkmated.sign(), Ordering::Less);
+    }
+
+    #[test]
+    fn test_wdl() {
+        assert_eq!(Value::Dtc(Dtc(3)).wdl(), Wdl::Win);
+        assert_eq!(Value::Dtc(Dtc(-3)).wdl(), Wdl::Loss);
+        assert_eq!(Value::Checkmated.wdl(), Wdl::Loss);
+        assert_eq!(Value::Draw.wdl(), Wdl::Draw);
+        assert!(Wdl::Loss < Wdl::Draw && Wdl::Draw < Wdl::Win);
+    }
+
+    #[test]
+    fn test_game_result() {
+        assert_eq!(Value::Dtc(Dtc(50)).game_result(), GameResult::Win);
+        assert_eq!(Value::Dtc(Dtc(51)).game_result(), GameResult::CursedWin);
+        assert_eq!(Value::Dtc(Dtc(-50)).game_result(), GameResult::Loss);
+        assert_eq!(Value::Dtc(Dtc(-51)).game_result(), GameResult::BlessedLoss);
+        assert_eq!(Value::Dtc(Dtc(0)).game_result(), GameResult::Loss);
+        assert_eq!(Value::Checkmated.game_result(), GameResult::Loss);
+        assert_eq!(Value::Draw.game_result(), GameResult::Draw);
+        assert!(GameResult::BlessedLoss < GameResult::Draw);
+        assert!(GameResult::CursedWin < GameResult::Win);
+    }
+
+    #[test]
+    fn test_bucketed() {
+        assert_eq!(Value::Dtc(Dtc(10)).bucketed(10), BucketedValue::WinShort);
+        assert_eq!(Value::Dtc(Dtc(11)).bucketed(10), BucketedValue::WinLong);
+        assert_eq!(Value::Dtc(Dtc(-10)).bucketed(10), BucketedValue::LossShort);
+        assert_eq!(Value::Dtc(Dtc(-11)).bucketed(10), BucketedValue::LossLong);
+        assert_eq!(Value::Dtc(Dtc(0)).bucketed(0), BucketedValue::LossShort);
+        assert_eq!(Value::Checkmated.bucketed(0), BucketedValue::LossShort);
+        assert_eq!(Value::Draw.bucketed(10), BucketedValue::Draw);
+        assert!(BucketedValue::LossShort < BucketedValue::LossLong);
+        assert!(BucketedValue::WinLong < BucketedValue::WinShort);
+    }
+
+    #[test]
+    fn test_bytes() {
+        for value in [
+            Value::Draw,
+            Value::Checkmated,
+            Value::Dtc(Dtc(0)),
+            Value::Dtc(Dtc(584)),
+            Value::Dtc(Dtc(-584)),
+            Value::Dtc(Dtc(32767)),
+            Value::Dtc(Dtc(-32766)),
+        ] {
+            assert_eq!(Value::from_bytes(value.to_bytes().unwrap()), value);
+        }
+        assert_eq!(Value::Draw.to_bytes(), Some([0x00, 0x80]));
+        assert_eq!(Value::Dtc(Dtc(1)).to_bytes(), Some([0x01, 0x00]));
+        assert_eq!(Value::Dtc(Dtc(32768)).to_bytes(), None);
+        assert_eq!(Value::Dtc(Dtc(-32767)).to_bytes(), None);
+    }
+
+    #[test]
+    fn test_relative_dtc() {
+        assert_eq!(relative_dtc(Color::White, 254), 254);
+        assert_eq!(relative_dtc(Color::Black, 254), -254);
+        assert_eq!(relative_dtc(Color::White, i32::MAX), i32::MAX);
+        assert_eq!(relative_dtc(Color::Black, i32::MAX), -i32::MAX);
+        assert_eq!(relative_dtc(Color::Black, i32::MIN), i32::MAX);
     }
 }
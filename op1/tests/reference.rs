@@ -1,5 +1,27 @@
-use op1::{Tablebase, Value};
-use shakmaty::{CastlingMode, Chess, fen::Fen};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::HashMap,
+    fs,
+    io::Cursor,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+#[cfg(unix)]
+use std::{
+    ffi::OsStr,
+    os::unix::{ffi::OsStrExt as _, fs::symlink},
+};
+
+use op1::{
+    CorruptPolicy, DrawKind, Dtc, FiftyMoveRule, MoveVerdict, PawnFileType, Phase, ProbeContext,
+    ProbeError, ProbeOutcome, ReadSeek, SideValue, TableKey, TableType, Tablebase, TablebaseError,
+    TieBreak, Value, Wdl, material_string,
+};
+use shakmaty::{
+    Board, ByRole, CastlingMode, Chess, Color, EnPassantMode, FromSetup as _, Move, Outcome,
+    Position as _, Role, Setup, Square, fen::Fen,
+};
 use test_log::test;
 
 fn open_tablebase() -> Tablebase {
@@ -18,6 +40,37 @@ fn assert_score(tb: &Tablebase, fen: &str, expected: Option<Value>) {
     assert_eq!(tb.probe(&pos).unwrap(), expected, "{fen}");
 }
 
+#[test]
+fn test_keys() {
+    let tb = open_tablebase();
+
+    let material = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_setup()
+        .board
+        .material();
+    assert!(tb.keys().any(|key| key.material() == material));
+    assert!(tb.keys().all(|key| tb.contains_material(&key.material())));
+
+    let missing = "4k3/8/8/8/8/8/8/QQQQK3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_setup()
+        .board
+        .material();
+    assert_eq!(tb.assert_loaded(&[material]), Ok(()));
+    assert_eq!(tb.assert_loaded(&[material, missing]), Err(vec![missing]));
+
+    let loaded = tb.loaded_subtable_count(&material);
+    let expected = op1::expected_subtable_count(&material);
+    assert!(loaded.mb_files > 0);
+    // All sub-tables of KBPvKPPPP are present.
+    assert_eq!(loaded.directories, expected.directories);
+    assert_eq!(loaded.mb_files, expected.mb_files);
+    assert!(loaded.high_dtc_files <= expected.high_dtc_files);
+}
+
 #[test]
 fn test_kbpkpppp() {
     let tb = open_tablebase();
@@ -25,42 +78,42 @@ fn test_kbpkpppp() {
     assert_score(
         &tb,
         "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1",
-        Some(Value::Dtc(53)),
+        Some(Value::Dtc(Dtc::new(53))),
     );
     assert_score(
         &tb,
         "8/7p/k7/8/8/5P2/P5PP/K2b4 w - - 0 1",
-        Some(Value::Dtc(42)),
+        Some(Value::Dtc(Dtc::new(42))),
     );
     assert_score(
         &tb,
         "8/2b5/8/8/3P4/pPP5/P7/2k1K3 w - - 0 1",
-        Some(Value::Dtc(-3)),
+        Some(Value::Dtc(Dtc::new(-3))),
     );
     assert_score(
         &tb,
         "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
-        Some(Value::Dtc(-1)),
+        Some(Value::Dtc(Dtc::new(-1))),
     );
     assert_score(
         &tb,
         "8/p1b5/8/8/3P4/1PP5/P7/1k2K3 w - - 0 1",
-        Some(Value::Dtc(-2)),
+        Some(Value::Dtc(Dtc::new(-2))),
     );
     assert_score(
         &tb,
         "8/p1b5/8/2PP4/PP6/8/8/1k2K3 b - - 0 1",
-        Some(Value::Dtc(-7)),
+        Some(Value::Dtc(Dtc::new(-7))),
     );
     assert_score(
         &tb,
         "8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1",
-        Some(Value::Dtc(6)),
+        Some(Value::Dtc(Dtc::new(6))),
     );
     assert_score(
         &tb,
         "8/2bp4/8/2PP4/PP6/8/8/1k2K3 w - - 0 1",
-        Some(Value::Dtc(4)),
+        Some(Value::Dtc(Dtc::new(4))),
     );
     assert_score(
         &tb,
@@ -71,7 +124,7 @@ fn test_kbpkpppp() {
     assert_score(
         &tb,
         "8/4p3/8/6P1/4PP2/5b2/7P/5k1K w - - 1 3",
-        Some(Value::Dtc(0)), // checkmate
+        Some(Value::Checkmated),
     );
 }
 
@@ -82,7 +135,7 @@ fn test_krbbpkqp() {
     assert_score(
         &tb,
         "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
-        Some(Value::Dtc(584)),
+        Some(Value::Dtc(Dtc::new(584))),
     );
 }
 
@@ -93,7 +146,7 @@ fn test_kbnnpkqp() {
     assert_score(
         &tb,
         "8/8/6B1/1K3p2/N3k1N1/8/5P2/2q5 w - - 0 1",
-        Some(Value::Dtc(304)),
+        Some(Value::Dtc(Dtc::new(304))),
     );
 }
 
@@ -117,7 +170,7 @@ fn test_krrnkrr() {
         "r7/5r1N/8/8/6k1/8/7R/3K1R2 w - - 0 1",
         "r7/5r1N/8/8/6k1/8/7R/3K3R w - - 0 1",
     ] {
-        assert_score(&tb, fen, Some(Value::Dtc(290)));
+        assert_score(&tb, fen, Some(Value::Dtc(Dtc::new(290))));
     }
 }
 
@@ -128,12 +181,12 @@ fn test_knnppkrpp_dp2() {
     assert_score(
         &tb,
         "1k2N3/1p1r4/3p4/3P4/8/8/KP6/N7 w - - 0 1",
-        Some(Value::Dtc(128)),
+        Some(Value::Dtc(Dtc::new(128))),
     );
     assert_score(
         &tb,
         "1k2N3/1p1r4/3p4/3P4/8/8/KP6/4N3 w - - 0 1",
-        Some(Value::Dtc(128)),
+        Some(Value::Dtc(Dtc::new(128))),
     );
 }
 
@@ -144,6 +197,1471 @@ fn test_krppknnpp_dp2() {
     assert_score(
         &tb,
         "n6k/6p1/4n1P1/6p1/8/3K4/5RP1/8 w - - 0 1",
-        Some(Value::Dtc(78)),
+        Some(Value::Dtc(Dtc::new(78))),
+    );
+}
+
+#[test]
+fn test_probe_position_and_children_in_one_pass() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+
+    let (parent, children) = tb.probe_position_and_children_in_one_pass(&pos).unwrap();
+    assert_eq!(parent, Some(Value::Dtc(Dtc::new(6))));
+    assert_eq!(children.len(), pos.legal_moves().len());
+    for (m, value) in children {
+        let mut after = pos.clone();
+        after.play_unchecked(&m);
+        assert_eq!(tb.probe(&after).unwrap(), value, "{m}");
+    }
+}
+
+#[test]
+fn test_probe_detailed() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/1kbp4/8/2PP4/PP6/8/8/4K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_detailed(&pos).unwrap(),
+        ProbeOutcome::Draw(DrawKind::TablebaseDraw)
+    );
+
+    let pos: Chess = "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_detailed(&pos).unwrap(),
+        ProbeOutcome::Draw(DrawKind::OneSidedTablebaseDraw)
+    );
+
+    let pos: Chess = "8/8/8/8/8/2k5/8/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_detailed(&pos).unwrap(),
+        ProbeOutcome::Draw(DrawKind::InsufficientMaterial)
+    );
+
+    let pos: Chess = "4k3/8/8/8/8/8/8/KQQQ4 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_detailed(&pos).unwrap(),
+        ProbeOutcome::TableMissing {
+            material: pos.board().material(),
+            pawn_file_type: None,
+        }
+    );
+
+    let pos: Chess = "8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_with_halfmove(&pos, 89).unwrap(),
+        ProbeOutcome::Dtc(Dtc::new(6))
+    );
+    assert_eq!(
+        tb.probe_with_halfmove(&pos, 90).unwrap(),
+        ProbeOutcome::Draw(DrawKind::FiftyMoveAdjusted)
+    );
+    assert_eq!(
+        tb.probe_with_fifty_move_rule(&pos, FiftyMoveRule::FromClock(90))
+            .unwrap(),
+        ProbeOutcome::Draw(DrawKind::FiftyMoveAdjusted)
+    );
+    assert_eq!(
+        tb.probe_with_fifty_move_rule(&pos, FiftyMoveRule::Strict)
+            .unwrap(),
+        ProbeOutcome::Dtc(Dtc::new(6))
+    );
+
+    // Cursed win: 105 plies to conversion.
+    let pos: Chess = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_with_fifty_move_rule(&pos, FiftyMoveRule::Ignore)
+            .unwrap(),
+        ProbeOutcome::Dtc(Dtc::new(53))
+    );
+    assert_eq!(
+        tb.probe_with_fifty_move_rule(&pos, FiftyMoveRule::Strict)
+            .unwrap(),
+        ProbeOutcome::Draw(DrawKind::FiftyMoveAdjusted)
+    );
+}
+
+#[test]
+fn test_probe_epd() {
+    let tb = open_tablebase();
+
+    assert_eq!(
+        tb.probe_epd("8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - bm Kd2; id \"test\";")
+            .unwrap(),
+        Some(Value::Dtc(Dtc::new(6)))
+    );
+    assert!(matches!(
+        tb.probe_epd("8/p1b5/8/2PP4/PP6/8/8/1k2K3 x - - bm Kd2;"),
+        Err(ProbeError::Fen(_))
+    ));
+    assert!(matches!(
+        tb.probe_epd("8/8/8/8/8/8/8/K6k w - - id \"kings\";"),
+        Ok(Some(Value::Draw))
+    ));
+}
+
+#[test]
+fn test_probe_fen() {
+    let tb = open_tablebase();
+
+    assert_eq!(
+        tb.probe_fen("8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1")
+            .unwrap(),
+        Some(Value::Dtc(Dtc::new(6)))
+    );
+    assert!(matches!(
+        tb.probe_fen("8/p1b5/8/2PP4/PP6/8/8/1k2K3 x - - 0 1"),
+        Err(ProbeError::Fen(_))
+    ));
+    assert!(matches!(
+        tb.probe_fen("8/8/8/8/8/8/8/K5kk w - - 0 1"),
+        Err(ProbeError::Position(_))
+    ));
+}
+
+#[test]
+fn test_probe_iter() {
+    let tb = open_tablebase();
+
+    let positions = [
+        "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1",
+        "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+    ]
+    .map(|fen| {
+        fen.parse::<Fen>()
+            .unwrap()
+            .into_position::<Chess>(CastlingMode::Chess960)
+            .unwrap()
+    });
+
+    let results: Vec<_> = tb.probe_iter(positions.clone()).collect();
+    assert_eq!(results.len(), 2);
+    for (pos, (probed, result)) in positions.iter().zip(results) {
+        assert_eq!(probed.board(), pos.board());
+        assert_eq!(result.unwrap(), tb.probe(pos).unwrap());
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_probe_stream() {
+    let tb = Arc::new(open_tablebase());
+
+    let pos: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let mut results = Arc::clone(&tb).probe_stream(rx);
+    tx.send(pos.clone()).await.unwrap();
+    drop(tx);
+
+    let (probed, result) = results.recv().await.unwrap();
+    assert_eq!(probed.board(), pos.board());
+    assert_eq!(result.unwrap(), Some(Value::Dtc(Dtc::new(-1))));
+    assert!(results.recv().await.is_none());
+}
+
+#[test]
+fn test_probe_many_fens() {
+    let tb = open_tablebase();
+
+    let results = tb.probe_many_fens(&[
+        "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1",
+        "not a fen",
+        "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+        "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1",
+    ]);
+    assert_eq!(results.len(), 4);
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &Some(Value::Dtc(Dtc::new(53)))
+    );
+    assert!(matches!(results[1], Err(ProbeError::Fen(_))));
+    assert_eq!(
+        results[2].as_ref().unwrap(),
+        &Some(Value::Dtc(Dtc::new(-1)))
+    );
+    assert_eq!(
+        results[3].as_ref().unwrap(),
+        &Some(Value::Dtc(Dtc::new(53)))
+    );
+}
+
+#[test]
+fn test_probe_board() {
+    let tb = open_tablebase();
+
+    let board: Board = "8/p1b5/8/2PP4/PP6/8/8/1k2K3".parse().unwrap();
+    assert_eq!(
+        tb.probe_board(&board, Color::White, None).unwrap(),
+        Some(Value::Dtc(Dtc::new(6)))
+    );
+    assert_eq!(
+        tb.probe_board(&board, Color::Black, None).unwrap(),
+        Some(Value::Dtc(Dtc::new(-7)))
+    );
+
+    let board: Board = "8/p1b5/8/2PP4/PP6/8/8/1K2K3".parse().unwrap();
+    assert!(tb.probe_board(&board, Color::White, None).is_err());
+}
+
+#[test]
+fn test_principal_variation() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+
+    let (m, value) = tb.best_move(&pos).unwrap().unwrap();
+    assert_eq!(value, Value::Dtc(Dtc::new(-1)));
+    assert_eq!(
+        tb.probe_and_best_move(&pos).unwrap(),
+        (Some(value), Some(m))
+    );
+
+    let line = tb.principal_variation(&pos).unwrap();
+    assert_eq!(line.len(), 2);
+    assert!(line[1].is_capture() || line[1].is_promotion());
+
+    assert_eq!(tb.phase(&pos).unwrap(), Some(Phase::Maneuvering));
+    let mut after = pos.clone();
+    after.play_unchecked(&line[0]);
+    assert_eq!(tb.phase(&after).unwrap(), Some(Phase::ConversionImminent));
+
+    let mut read_ahead = Tablebase::new();
+    read_ahead.set_read_ahead(16);
+    read_ahead.add_path("../tables").unwrap();
+    assert_eq!(read_ahead.principal_variation(&pos).unwrap(), line);
+
+    let mut mmap_read_ahead = Tablebase::new();
+    mmap_read_ahead.set_mmap(true);
+    mmap_read_ahead.set_read_ahead(16);
+    mmap_read_ahead.add_path("../tables").unwrap();
+    assert_eq!(mmap_read_ahead.principal_variation(&pos).unwrap(), line);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_high_dtc_without_mb() {
+    // Simulate a partial install with only the .hi files of krbbpkqp.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("high_dtc_without_mb");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|ext| ext == "hi") {
+                symlink(
+                    fs::canonicalize(&file).unwrap(),
+                    root.join(name).join(file.file_name().unwrap()),
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    let mut tb = Tablebase::new();
+    assert!(tb.add_path(&root).unwrap() > 0);
+
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+}
+
+#[test]
+fn test_validate_material_loadable() {
+    let tb = open_tablebase();
+
+    let material = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_setup()
+        .board
+        .material();
+    assert_eq!(
+        tb.validate_material_loadable(&material).unwrap(),
+        Some(true)
+    );
+
+    let board: Board = "4k3/8/8/8/8/8/8/QQQQK3".parse().unwrap();
+    let material = board.material();
+    assert_eq!(
+        tb.validate_material_loadable(&material).unwrap(),
+        Some(false)
+    );
+}
+
+#[test]
+fn test_probe_pov() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_pov(&pos, Color::White).unwrap(),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(
+        tb.probe_pov(&pos, Color::Black).unwrap(),
+        Some(Ordering::Less)
+    );
+}
+
+#[test]
+fn test_probe_signed() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/p1b5/8/2PP4/PP6/8/8/1k2K3 b - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let signed = tb.probe_signed(&pos).unwrap().unwrap();
+    assert_eq!(signed.turn, Color::Black);
+    assert_eq!(signed.side_to_move_relative, -7);
+    assert_eq!(signed.white_relative, 7);
+    assert_eq!(signed.wdl, Wdl::Loss);
+}
+
+#[test]
+fn test_index() {
+    let index = Path::new(env!("CARGO_TARGET_TMPDIR")).join("index.txt");
+
+    let mut tb = Tablebase::new();
+    let num = tb.add_path("../tables").unwrap();
+    tb.save_index(&index).unwrap();
+
+    let mut tb = Tablebase::new();
+    assert_eq!(tb.load_index(&index).unwrap(), num);
+    assert_score(
+        &tb,
+        "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(53))),
+    );
+
+    fs::write(&index, "not/a/table.mb\n").unwrap();
+    assert_eq!(Tablebase::new().load_index(&index).unwrap(), 0);
+}
+
+#[test]
+fn test_role_values() {
+    let tb = open_tablebase();
+    let mut weighted = open_tablebase();
+    weighted.set_role_values(ByRole {
+        pawn: 2,
+        knight: 3,
+        bishop: 3,
+        rook: 5,
+        queen: 9,
+        king: 0,
+    });
+
+    // Both sides have equal strength by the default values, but the pawns
+    // win. Weighting pawns higher probes the winning side first.
+    for fen in [
+        "8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1",
+        "8/p1b5/8/2PP4/PP6/8/8/1k2K3 b - - 0 1",
+        "1K2k3/8/8/pp6/2pp4/8/P1B5/8 w - - 0 1",
+        "1K2k3/8/8/pp6/2pp4/8/P1B5/8 b - - 0 1",
+    ] {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+        assert_eq!(
+            tb.probe(&pos).unwrap(),
+            weighted.probe(&pos).unwrap(),
+            "{fen}"
+        );
+    }
+
+    assert!(weighted.stats().flipped_probes() < tb.stats().flipped_probes());
+}
+
+#[test]
+fn test_ignore_castling_rights() {
+    let mut tb = open_tablebase();
+
+    let pos: Chess = "r3k2r/8/8/8/8/8/8/R3K1NR w KQkq - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let without_rights: Chess = "r3k2r/8/8/8/8/8/8/R3K1NR w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(tb.probe_detailed(&pos).unwrap(), ProbeOutcome::OutOfScope);
+
+    tb.set_ignore_castling_rights(true);
+    let value = tb.probe(&without_rights).unwrap();
+    assert!(value.is_some());
+    assert_eq!(tb.probe(&pos).unwrap(), value);
+}
+
+#[test]
+fn test_add_path_list() {
+    let mut tb = Tablebase::new();
+    let (num, errors) = tb.add_path_list("../tables::../does-not-exist");
+    assert!(num > 0);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, Path::new("../does-not-exist"));
+    assert!(matches!(errors[0].1, TablebaseError::Io(_)));
+
+    assert!(matches!(
+        tb.add_path("Cargo.toml"),
+        Err(TablebaseError::InvalidPath { .. })
+    ));
+
+    // Paths with interior NUL bytes are rejected, not a panic.
+    #[cfg(unix)]
+    {
+        let nul = Path::new(OsStr::from_bytes(b"../tables\0/kqk_out"));
+        assert!(matches!(tb.add_path(nul), Err(TablebaseError::Io(_))));
+        assert!(tb.add_path_recursive(nul).is_err());
+    }
+}
+
+#[test]
+fn test_tie_break() {
+    let mut tb = open_tablebase();
+    tb.set_tie_break(TieBreak::Uci);
+
+    let pos: Chess = "8/1kbp4/8/2PP4/PP6/8/8/4K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+
+    let uci = |m: &Move| m.to_uci(CastlingMode::Standard).to_string();
+    let optimal: Vec<String> = pos
+        .legal_moves()
+        .iter()
+        .filter(|m| tb.move_preserves(&pos, m).unwrap() == Some(MoveVerdict::Optimal))
+        .map(uci)
+        .collect();
+    assert!(optimal.len() > 1);
+
+    for _ in 0..3 {
+        let (m, _) = tb.best_move(&pos).unwrap().unwrap();
+        assert_eq!(Some(&uci(&m)), optimal.iter().min());
+    }
+}
+
+#[test]
+fn test_read_value() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let mb_info = tb.mb_info(&pos).unwrap().unwrap();
+    let (index, _) = mb_info.parity_indices[0];
+    let key = tb
+        .keys()
+        .find(|key| {
+            key.material() == pos.board().material()
+                && key.side() == Color::White
+                && key.kk_index() == mb_info.kk_index as u32
+                && key.pawn_file_type() == PawnFileType::Free
+                && key.table_type() == TableType::Mb
+        })
+        .unwrap();
+    assert_eq!(
+        tb.read_value(key, index).unwrap(),
+        Some(Value::Dtc(Dtc::new(53)))
     );
 }
+
+#[test]
+fn test_probe_null_move() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let null: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 b - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(tb.probe_null_move(&pos).unwrap(), tb.probe(&null).unwrap());
+    assert!(tb.probe_null_move(&pos).unwrap().is_some());
+
+    let check: Chess = "8/8/8/8/3P4/pPP3b1/P7/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(tb.probe_null_move(&check).unwrap(), None);
+}
+
+#[test]
+fn test_en_passant_mode() {
+    // The pawn on d5 is pinned, so e6 is only pseudo-legal.
+    let pos: Chess = "7k/1K6/8/3Pp3/8/1PP2b2/P7/8 w - e6 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let no_ep: Chess = "7k/1K6/8/3Pp3/8/1PP2b2/P7/8 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(pos.ep_square(EnPassantMode::Legal), None);
+    assert!(pos.ep_square(EnPassantMode::PseudoLegal).is_some());
+
+    let mut tb = open_tablebase();
+    assert_eq!(tb.mb_info(&pos).unwrap(), tb.mb_info(&no_ep).unwrap());
+    assert_eq!(tb.probe(&pos).unwrap(), tb.probe(&no_ep).unwrap());
+
+    tb.set_en_passant_mode(EnPassantMode::PseudoLegal);
+    // mbeval indexes positions with an en passant square separately.
+    assert_ne!(tb.mb_info(&pos).unwrap(), tb.mb_info(&no_ep).unwrap());
+    assert!(tb.probe(&pos).unwrap().is_some());
+}
+
+#[test]
+fn test_add_path_report() {
+    let mut tb = Tablebase::new();
+    let report = tb.add_path_report("../tables").unwrap();
+    assert_eq!(report.tables(), tb.keys().count());
+    assert_eq!(
+        report.mb_tables,
+        tb.keys()
+            .filter(|key| key.table_type() == TableType::Mb)
+            .count()
+    );
+    assert!(report.high_dtc_tables > 0);
+    assert!(report.materials > 0);
+
+    let mut again = Tablebase::new();
+    assert_eq!(again.add_path("../tables").unwrap(), report.tables());
+}
+
+#[test]
+fn test_probe_squares() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let mut squares = [0; 64];
+    for (sq, piece) in pos.board() {
+        let code = match piece.role {
+            Role::Pawn => 1,
+            Role::Knight => 2,
+            Role::Bishop => 4,
+            Role::Rook => 8,
+            Role::Queen => 12,
+            Role::King => 16,
+        };
+        squares[usize::from(sq)] = piece.color.fold_wb(code, -code);
+    }
+    assert_eq!(tb.probe(&pos).unwrap(), Some(Value::Dtc(Dtc::new(53))));
+    assert_eq!(
+        tb.probe_squares(&squares, Color::White, None).unwrap(),
+        Some(SideValue::Dtc(53))
+    );
+
+    squares[usize::from(Square::E4)] = 3;
+    assert!(tb.probe_squares(&squares, Color::White, None).is_err());
+}
+
+#[test]
+fn test_probe_raw_squares() {
+    let tb = open_tablebase();
+
+    // 8/1pp5/p1p5/8/B7/8/P6k/2K5 w, in the square encoding of mbeval.
+    let mut squares = [0; 64];
+    squares[usize::from(Square::C1)] = 16;
+    squares[usize::from(Square::A2)] = 1;
+    squares[usize::from(Square::H2)] = -16;
+    squares[usize::from(Square::A4)] = 4;
+    squares[usize::from(Square::A6)] = -1;
+    squares[usize::from(Square::C6)] = -1;
+    squares[usize::from(Square::B7)] = -1;
+    squares[usize::from(Square::C7)] = -1;
+    assert_eq!(
+        tb.probe_squares(&squares, Color::White, None).unwrap(),
+        Some(SideValue::Dtc(53))
+    );
+
+    // Without the white pieces, there is no table to consult.
+    squares[usize::from(Square::A2)] = 0;
+    squares[usize::from(Square::A4)] = 0;
+    assert_eq!(
+        tb.probe_squares(&squares, Color::White, None).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_canonical() {
+    let tb = open_tablebase();
+
+    // Black is stronger.
+    let pos: Chess = "7q/8/8/8/8/1k6/8/K7 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let canonical = tb.canonical(&pos);
+    let expected: Board = "k7/8/1K6/8/8/8/8/7Q".parse().unwrap();
+    assert_eq!(canonical.turn(), Color::Black);
+    assert_eq!(canonical.board(), &expected);
+    assert_eq!(tb.probe(&canonical).unwrap(), tb.probe(&pos).unwrap());
+
+    // Equal strength.
+    let pos: Chess = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(tb.canonical(&pos).board(), pos.board());
+}
+
+#[test]
+fn test_corrupt_policy() {
+    // Simulate a broken install with truncated .mb files of krbbpkqp.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("corrupt_policy");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|ext| ext == "mb") {
+                fs::write(root.join(name).join(file.file_name().unwrap()), b"corrupt").unwrap();
+            }
+        }
+    }
+
+    let mut tb = Tablebase::new();
+    assert!(tb.add_path(&root).unwrap() > 0);
+
+    let report = tb.verify();
+    assert_eq!(report.opened, 0);
+    assert!(!report.failed.is_empty());
+    assert!(
+        report
+            .failed
+            .iter()
+            .all(|(_, err)| matches!(err, TablebaseError::CorruptTable { .. }))
+    );
+
+    let pos: Chess = "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert!(matches!(
+        tb.probe(&pos),
+        Err(ProbeError::Tablebase(TablebaseError::CorruptTable { path, .. }))
+            if path.starts_with(&root)
+    ));
+
+    tb.set_corrupt_policy(CorruptPolicy::Skip);
+    assert_eq!(tb.probe_detailed(&pos).unwrap(), ProbeOutcome::OutOfScope);
+    assert_eq!(tb.probe(&pos).unwrap(), None);
+}
+
+#[test]
+fn test_probe_with_context() {
+    let tb = open_tablebase();
+    let mut ctx = ProbeContext::new().unwrap();
+
+    for fen in [
+        "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+        "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1",
+        "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+    ] {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+        assert_eq!(
+            tb.probe_with_context(&pos, &mut ctx).unwrap(),
+            tb.probe(&pos).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_child_value_histogram() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/1pp5/p1p5/8/B7/8/P6k/2K5 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let histogram = tb.child_value_histogram(&pos).unwrap();
+    let (_, best) = tb.best_move(&pos).unwrap().unwrap();
+    assert_eq!(histogram[0].0, best);
+    assert_eq!(
+        histogram
+            .iter()
+            .map(|&(_, count)| count as usize)
+            .sum::<usize>(),
+        pos.legal_moves().len()
+    );
+    assert!(histogram.is_sorted_by_key(|&(value, _)| Reverse(value.to_score(1000))));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_wdl_without_high_dtc() {
+    // Simulate a partial install with only the .mb files of krbbpkqp.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("wdl_without_high_dtc");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|ext| ext == "mb") {
+                symlink(
+                    fs::canonicalize(&file).unwrap(),
+                    root.join(name).join(file.file_name().unwrap()),
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    let mut tb = Tablebase::new();
+    assert!(tb.add_path(&root).unwrap() > 0);
+
+    let pos: Chess = "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(tb.probe(&pos).unwrap(), None);
+    assert_eq!(tb.probe_wdl(&pos).unwrap(), None);
+
+    tb.set_wdl_without_high_dtc(true);
+    assert_eq!(tb.probe_wdl(&pos).unwrap(), Some(Wdl::Win));
+    assert_eq!(tb.probe(&pos).unwrap(), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff() {
+    // Two installs with the same single table, one of them corrupt.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("diff");
+    let _ = fs::remove_dir_all(&root);
+    let (name, file) = fs::read_dir("../tables")
+        .unwrap()
+        .map(|directory| directory.unwrap().path())
+        .filter(|directory| directory.to_str().unwrap().contains("/krbbpkqp_"))
+        .find_map(|directory| {
+            let file = fs::read_dir(&directory)
+                .unwrap()
+                .map(|file| file.unwrap().path())
+                .find(|file| file.extension().is_some_and(|ext| ext == "mb"))?;
+            Some((directory.file_name().unwrap().to_owned(), file))
+        })
+        .unwrap();
+    for install in ["a", "b"] {
+        fs::create_dir_all(root.join(install).join(&name)).unwrap();
+    }
+    let target = root.join("a").join(&name).join(file.file_name().unwrap());
+    symlink(fs::canonicalize(&file).unwrap(), target).unwrap();
+    let target = root.join("b").join(&name).join(file.file_name().unwrap());
+    fs::write(target, b"corrupt").unwrap();
+
+    let mut a = Tablebase::new();
+    assert_eq!(a.add_path(root.join("a")).unwrap(), 1);
+    let mut b = Tablebase::new();
+    assert_eq!(b.add_path(root.join("b")).unwrap(), 1);
+
+    assert!(a.diff(&b).is_empty());
+    assert_eq!(a.diff_contents(&b).unwrap().len(), 1);
+    assert!(a.diff_contents(&a).unwrap().is_empty());
+
+    let full = open_tablebase();
+    let diff = a.diff(&full);
+    assert!(diff.only_in_self.is_empty());
+    assert_eq!(diff.only_in_other.len(), full.keys().count() - 1);
+}
+
+#[test]
+fn test_probe_after_captures() {
+    let tb = open_tablebase();
+
+    // Kxe2 is the only capture and reaches an installed endgame.
+    let pos: Chess = "8/2b5/8/8/3P4/pPP5/P3n3/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let values = tb.probe_after_captures(&pos).unwrap();
+    assert_eq!(values.len(), 1);
+    let (m, value) = &values[0];
+    assert_eq!(m.to(), Square::E2);
+    let mut after = pos.clone();
+    after.play_unchecked(m);
+    let child = tb.probe(&after).unwrap().unwrap();
+    assert_eq!(value.sign(), child.sign().reverse());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_index_to_json() {
+    let tb = open_tablebase();
+
+    let json: serde_json::Value = serde_json::from_str(&tb.index_to_json()).unwrap();
+    let entries = json.as_array().unwrap();
+    assert_eq!(entries.len(), tb.keys().count());
+    let entry = entries
+        .iter()
+        .find(|entry| entry["material"] == "krbbpkqp")
+        .unwrap();
+    assert!(entry["path"].as_str().unwrap().contains("krbbpkqp"));
+    assert!(entry["kk_index"].is_u64());
+    assert!(matches!(entry["side"].as_str(), Some("w" | "b")));
+}
+
+#[test]
+fn test_builtin_solver() {
+    let tb = open_tablebase();
+    let mut builtin = Tablebase::new();
+    builtin.set_builtin_solver(true);
+
+    for role in [Role::Queen, Role::Rook] {
+        for strong in Color::ALL {
+            for sq in Square::ALL {
+                for king in Square::ALL {
+                    for turn in Color::ALL {
+                        let mut board = Board::empty();
+                        board.set_piece_at(Square::C3, Role::King.of(strong));
+                        board.set_piece_at(sq, role.of(strong));
+                        board.set_piece_at(king, Role::King.of(!strong));
+                        if board.occupied().count() != 3 {
+                            continue;
+                        }
+                        let setup = Setup {
+                            board,
+                            turn,
+                            ..Setup::empty()
+                        };
+                        let Ok(pos) = Chess::from_setup(setup, CastlingMode::Standard) else {
+                            continue;
+                        };
+                        assert_eq!(
+                            builtin.probe(&pos).unwrap(),
+                            tb.probe(&pos).unwrap(),
+                            "{}",
+                            Fen::from_position(pos.clone(), EnPassantMode::Legal)
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_free_pawns_only() {
+    // Simulate an install without sub-tables for opposing pawns. The
+    // unconstrained table is always tried first, so it answers on its own.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("free_pawns_only");
+    let _ = fs::remove_dir_all(&root);
+    for name in ["kppppkbp_out", "kbpkpppp_out"] {
+        let directory = Path::new("../tables").join(name);
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            symlink(
+                fs::canonicalize(&file).unwrap(),
+                root.join(name).join(file.file_name().unwrap()),
+            )
+            .unwrap();
+        }
+    }
+
+    let mut tb = Tablebase::new();
+    assert!(tb.add_path(&root).unwrap() > 0);
+    assert!(
+        tb.keys()
+            .all(|key| key.pawn_file_type() == PawnFileType::Free)
+    );
+
+    // Opposing pawns on the a-file.
+    let pos: Chess = "8/p1b5/8/2PP4/PP6/8/8/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_ne!(
+        tb.mb_info(&pos).unwrap().unwrap().pawn_file_type,
+        PawnFileType::Free
+    );
+    assert_eq!(
+        tb.probe(&pos).unwrap(),
+        open_tablebase().probe(&pos).unwrap()
+    );
+    assert!(tb.probe(&pos).unwrap().is_some());
+}
+
+#[test]
+fn test_flipped_probes() {
+    let tb = open_tablebase();
+    assert_eq!(tb.stats().flipped_probes(), 0);
+
+    // Draws are only known after probing both sides.
+    assert_score(
+        &tb,
+        "8/1kbp4/8/2PP4/PP6/8/8/4K3 w - - 0 1",
+        Some(Value::Draw),
+    );
+    assert_eq!(tb.stats().flipped_probes(), 1);
+    assert_eq!(tb.stats().draws(), 1);
+}
+
+#[test]
+fn test_probe_san_line() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_san_line(&pos, &[]).unwrap(),
+        Some(Value::Dtc(Dtc::new(-1)))
+    );
+
+    let after: Chess = "8/2b5/8/8/3P4/pPP5/P4K2/1k6 b - - 1 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    assert_eq!(
+        tb.probe_san_line(&pos, &["Kf2"]).unwrap(),
+        tb.probe(&after).unwrap()
+    );
+
+    assert!(matches!(
+        tb.probe_san_line(&pos, &["Kf2", "Kb2", "Ka1"]),
+        Err(ProbeError::San { ply: 2, .. })
+    ));
+    assert!(matches!(
+        tb.probe_san_line(&pos, &["Kf9"]),
+        Err(ProbeError::San { ply: 0, .. })
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prefer_high_dtc() {
+    // Simulate .mb files of krbbpkqp that claim a max DTC of 254, so that
+    // their entries of 254 are taken as exact.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("prefer_high_dtc");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            let target = root.join(name).join(file.file_name().unwrap());
+            if file.extension().is_some_and(|ext| ext == "mb") {
+                let mut data = fs::read(&file).unwrap();
+                data[44..48].copy_from_slice(&254u32.to_le_bytes());
+                fs::write(target, data).unwrap();
+            } else {
+                symlink(fs::canonicalize(&file).unwrap(), target).unwrap();
+            }
+        }
+    }
+
+    let mut tb = Tablebase::new();
+    assert!(tb.add_path(&root).unwrap() > 0);
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(254))),
+    );
+
+    tb.set_prefer_high_dtc(true);
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+}
+
+#[test]
+fn test_preload_all_with_progress() {
+    let tb = open_tablebase();
+    let total = tb.keys().count();
+    let mut calls = Vec::new();
+    let errors = tb.preload_all_with_progress(4, |done, total| calls.push((done, total)));
+    assert!(errors.is_empty());
+    assert_eq!(calls.len(), total);
+    assert_eq!(calls.last(), Some(&(total, total)));
+
+    // Failures are collected.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("preload_all_with_progress");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|ext| ext == "mb") {
+                fs::write(root.join(name).join(file.file_name().unwrap()), b"corrupt").unwrap();
+            }
+        }
+    }
+    let mut tb = Tablebase::new();
+    let added = tb.add_path(&root).unwrap();
+    assert!(added > 0);
+    assert_eq!(tb.preload_all_with_progress(2, |_, _| ()).len(), added);
+}
+
+#[test]
+fn test_reader_factory() {
+    // Serve the tables of krbbpkqp from memory, with an index pointing to
+    // paths that do not exist.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("reader_factory");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let mut tables = HashMap::new();
+    let mut index = String::new();
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            let Some(key) = TableKey::from_path(&file) else {
+                continue;
+            };
+            tables.insert(key, fs::read(&file).unwrap());
+            let missing = root
+                .join("missing")
+                .join(name)
+                .join(file.file_name().unwrap());
+            index.push_str(&format!("{}\n", missing.display()));
+        }
+    }
+    fs::write(root.join("index"), index).unwrap();
+
+    let mut tb = Tablebase::new().with_reader_factory(move |key: &TableKey| {
+        Ok(Box::new(Cursor::new(tables[key].clone())) as Box<dyn ReadSeek>)
+    });
+    assert!(tb.load_index(root.join("index")).unwrap() > 0);
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+    assert!(tb.max_dtc().unwrap().unwrap() >= 584);
+}
+
+#[test]
+fn test_terminal_positions_agree_with_outcome() {
+    fn collect(pos: &Chess, depth: u32, terminal: &mut Vec<Chess>) {
+        if pos.is_game_over() {
+            terminal.push(pos.clone());
+        } else if depth > 0 {
+            for m in pos.legal_moves() {
+                let mut child = pos.clone();
+                child.play_unchecked(&m);
+                collect(&child, depth - 1, terminal);
+            }
+        }
+    }
+
+    let tb = open_tablebase();
+
+    let mut checked = 0;
+    for fen in [
+        "8/4p3/8/6P1/4PP2/5b2/7P/5k1K w - - 1 3",
+        "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+        "8/2b5/8/8/3P4/pPP5/P7/2k1K3 w - - 0 1",
+        "8/p1b5/8/8/3P4/1PP5/P7/1k2K3 w - - 0 1",
+    ] {
+        let root: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+        let mut terminal = Vec::new();
+        collect(&root, 3, &mut terminal);
+
+        for pos in terminal {
+            let fen = Fen::from_position(pos.clone(), EnPassantMode::Legal);
+            let value = tb.probe(&pos).unwrap();
+            match pos.outcome() {
+                Some(Outcome::Decisive { .. }) => {
+                    assert_eq!(value, Some(Value::Checkmated), "{fen}");
+                }
+                Some(Outcome::Draw) => {
+                    // Stalemates need tables, which are only known to be
+                    // installed for the material of the root.
+                    if !pos.is_insufficient_material()
+                        && pos.board().material() != root.board().material()
+                    {
+                        continue;
+                    }
+                    assert_eq!(value, Some(Value::Draw), "{fen}");
+                }
+                None => unreachable!(),
+            }
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_reload_changed() {
+    // Simulate a download in progress, with tables of krbbpkqp arriving
+    // after the others.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("reload_changed");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let mut later = Vec::new();
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = fs::canonicalize(directory.unwrap().path()).unwrap();
+        let link = root.join(directory.file_name().unwrap());
+        if directory
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("krbbpkqp_")
+        {
+            later.push((directory, link));
+        } else {
+            symlink(&directory, &link).unwrap();
+        }
+    }
+    assert!(!later.is_empty());
+
+    let mut tb = Tablebase::new();
+    let before = tb.add_path(&root).unwrap();
+    assert!(before > 0);
+    assert_eq!(tb.reload_changed(), 0);
+    assert_score(&tb, "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1", None);
+
+    for (directory, link) in later {
+        symlink(directory, link).unwrap();
+    }
+    let added = tb.reload_changed();
+    assert!(added > 0);
+    assert_eq!(tb.keys().count(), before + added);
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+    assert_eq!(tb.reload_changed(), 0);
+}
+
+#[test]
+fn test_probe_moves() {
+    let tb = open_tablebase();
+
+    let pos: Chess = "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1"
+        .parse::<Fen>()
+        .unwrap()
+        .into_position(CastlingMode::Chess960)
+        .unwrap();
+    let moves = tb.probe_moves(&pos).unwrap();
+    assert_eq!(moves.len(), pos.legal_moves().len());
+
+    let (best, value) = tb.best_move(&pos).unwrap().unwrap();
+    assert_eq!(value, Value::Dtc(Dtc::new(-1)));
+    assert!(moves.contains(&(best, Some(value))));
+}
+
+#[test]
+fn test_scope_and_tables() {
+    let tb = open_tablebase();
+
+    for (fen, in_scope, has_tables) in [
+        ("8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1", true, true),
+        ("4k3/8/8/8/8/8/8/KQQQ4 w - - 0 1", true, false),
+        ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", true, true),
+        ("8/8/8/8/8/2k5/8/2K5 w - - 0 1", true, true),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            false,
+            false,
+        ),
+    ] {
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+        assert_eq!(tb.is_in_scope(&pos), in_scope, "{fen}");
+        assert_eq!(tb.has_tables_for(&pos), has_tables, "{fen}");
+    }
+}
+
+#[test]
+fn test_materials() {
+    let tb = open_tablebase();
+    let materials = tb.materials();
+    assert!(!materials.is_empty());
+    assert!(
+        materials
+            .iter()
+            .all(|material| tb.contains_material(material))
+    );
+    assert!(
+        materials
+            .iter()
+            .any(|material| material_string(material) == "KRBBPKQP")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_add_path_recursive() {
+    // Simulate an install organized in subdirectories by number of pieces.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("add_path_recursive");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = fs::canonicalize(directory.unwrap().path()).unwrap();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        let Some(material) = name.split('_').next() else {
+            continue;
+        };
+        let parent = root.join(material.len().to_string());
+        fs::create_dir_all(&parent).unwrap();
+        symlink(&directory, parent.join(name)).unwrap();
+    }
+
+    let mut tb = Tablebase::new();
+    assert_eq!(tb.add_path(&root).unwrap(), 0);
+    let report = tb.add_path_recursive(&root).unwrap();
+    assert_eq!(report.tables(), open_tablebase().keys().count());
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+}
+
+#[test]
+fn test_verify() {
+    let tb = open_tablebase();
+    let report = tb.verify();
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.opened, tb.keys().count());
+}
+
+#[test]
+fn test_with_capacity() {
+    let mut tb = Tablebase::with_capacity(1);
+    assert!(tb.add_path("../tables").unwrap() > 0);
+
+    // Alternate between materials, so that tables are evicted and reopened.
+    for _ in 0..2 {
+        assert_score(
+            &tb,
+            "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+            Some(Value::Dtc(Dtc::new(584))),
+        );
+        assert_score(
+            &tb,
+            "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+            Some(Value::Dtc(Dtc::new(-1))),
+        );
+    }
+}
+
+#[test]
+fn test_mmap() {
+    let mut tb = Tablebase::new();
+    tb.set_mmap(true);
+    assert!(tb.add_path("../tables").unwrap() > 0);
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+    assert!(tb.verify().is_ok());
+
+    // Truncated files must not be read out of bounds.
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("mmap");
+    let _ = fs::remove_dir_all(&root);
+    for directory in fs::read_dir("../tables").unwrap() {
+        let directory = directory.unwrap().path();
+        let name = directory.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("krbbpkqp_") {
+            continue;
+        }
+        fs::create_dir_all(root.join(name)).unwrap();
+        for file in fs::read_dir(&directory).unwrap() {
+            let file = file.unwrap().path();
+            let data = fs::read(&file).unwrap();
+            let truncated = &data[..data.len() / 2];
+            fs::write(root.join(name).join(file.file_name().unwrap()), truncated).unwrap();
+        }
+    }
+
+    let mut tb = Tablebase::new();
+    tb.set_mmap(true);
+    assert!(tb.add_path(&root).unwrap() > 0);
+    let report = tb.verify();
+    assert_eq!(report.opened, 0);
+    assert!(
+        report
+            .failed
+            .iter()
+            .all(|(_, err)| matches!(err, TablebaseError::CorruptTable { .. }))
+    );
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_add_path_parallel() {
+    let mut tb = Tablebase::new();
+    let report = tb.add_path_parallel("../tables").unwrap();
+    assert_eq!(
+        report,
+        Tablebase::new().add_path_report("../tables").unwrap()
+    );
+    assert_score(
+        &tb,
+        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+        Some(Value::Dtc(Dtc::new(584))),
+    );
+    assert!(matches!(
+        tb.add_path_parallel("../tables/does-not-exist"),
+        Err(TablebaseError::Io(_))
+    ));
+}
+
+#[test]
+fn test_concurrent_probes() {
+    // Count how often each table is opened.
+    let mut files = HashMap::new();
+    for directory in fs::read_dir("../tables").unwrap() {
+        for file in fs::read_dir(directory.unwrap().path()).unwrap() {
+            let file = file.unwrap().path();
+            if let Some(key) = TableKey::from_path(&file) {
+                files.insert(key, file);
+            }
+        }
+    }
+    let opened = Arc::new(Mutex::new(HashMap::<TableKey, usize>::new()));
+    let factory_opened = Arc::clone(&opened);
+    let mut tb = Tablebase::new().with_reader_factory(move |key: &TableKey| {
+        *factory_opened.lock().unwrap().entry(*key).or_default() += 1;
+        Ok(Box::new(fs::File::open(&files[key])?) as Box<dyn ReadSeek>)
+    });
+    assert!(tb.add_path("../tables").unwrap() > 0);
+
+    let tb = &tb;
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(move || {
+                for (fen, value) in [
+                    (
+                        "R7/8/8/8/7q/2K1B2p/7P/2Bk4 w - - 0 1",
+                        Value::Dtc(Dtc::new(584)),
+                    ),
+                    (
+                        "8/2b5/8/8/3P4/pPP5/P7/1k2K3 w - - 0 1",
+                        Value::Dtc(Dtc::new(-1)),
+                    ),
+                    (
+                        "8/2b5/8/8/3P4/pPP5/P7/2k1K3 w - - 0 1",
+                        Value::Dtc(Dtc::new(-3)),
+                    ),
+                ] {
+                    assert_score(tb, fen, Some(value));
+                }
+            });
+        }
+    });
+
+    let opened = opened.lock().unwrap();
+    assert!(!opened.is_empty());
+    assert!(opened.values().all(|&n| n == 1), "{opened:?}");
+}
@@ -0,0 +1,38 @@
+use std::env;
+
+use op1::{Dtc, Tablebase, Value};
+use shakmaty::{CastlingMode, Chess, fen::Fen};
+use test_log::test;
+
+#[test]
+fn test_regression_corpus() {
+    let Some(path) = env::var_os("OP1_TABLES") else {
+        tracing::warn!("OP1_TABLES not set, skipping regression corpus");
+        return;
+    };
+
+    let mut tb = Tablebase::new();
+    assert!(tb.add_path(path).unwrap() > 0);
+
+    for line in include_str!("data/regression.csv").lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (fen, expected) = line.split_once(',').expect("fen,expected");
+        let expected = match expected {
+            "none" => None,
+            "draw" => Some(Value::Draw),
+            "checkmated" => Some(Value::Checkmated),
+            dtc => Some(Value::Dtc(Dtc::new(dtc.parse().expect("dtc")))),
+        };
+
+        let pos: Chess = fen
+            .parse::<Fen>()
+            .unwrap()
+            .into_position(CastlingMode::Chess960)
+            .unwrap();
+
+        assert_eq!(tb.probe(&pos).unwrap(), expected, "{fen}");
+    }
+}